@@ -0,0 +1,599 @@
+//! The `--where` expression language: a boolean expression over the fields of an event,
+//! such as `ts in 1s..2s and (cat == "gfx" or name ~ "Vsync.*") and pid != 5`.
+//!
+//! Tests compare a field with a value and combine with `and`, `or`, `not` and
+//! parentheses, `and` binding tighter than `or`. The fields are:
+//! - `ts`: the event timestamp, compared with a window bound (`ts >= +500ms`) or tested
+//!   against a window (`ts in 40%..45%`)
+//! - `cat` and `name`: compared as strings with `==` and `!=`, or matched against a
+//!   regular expression with `~`
+//! - `pid` and `tid`: the process and thread koids, compared as numbers
+//! - `arg.NAME`: the argument called `NAME`, compared as a string or number, or matched
+//!   against a regular expression with `~`. Events without the argument fail the test
+//!
+//! Values are either bare words or double-quoted strings, in which `\"` and `\\` escape a
+//! quote and a backslash.
+
+use crate::{
+    filter::{ArgOp, ArgPredicate, ArgValue},
+    time::{Bound, TraceClock, Window},
+};
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// What an expression needs to know about an event. Only the fields a test reaches are
+/// asked for, so implementations can resolve them lazily.
+pub trait EventFields {
+    fn timestamp(&self) -> u64;
+    fn category(&self) -> Result<&str>;
+    fn name(&self) -> Result<&str>;
+    fn process_koid(&self) -> Result<u64>;
+    fn thread_koid(&self) -> Result<u64>;
+    /// Calls `f` with the name and value of each argument until it returns true,
+    /// returning whether it did.
+    fn any_arg(&self, f: &mut dyn FnMut(&str, ArgValue) -> bool) -> Result<bool>;
+}
+
+/// A parsed expression.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Test(Test),
+}
+
+/// A single comparison of an event field.
+#[derive(Clone, Debug)]
+pub enum Test {
+    /// `ts in START..END`, inclusive at both ends.
+    TsIn(Time, Time),
+    Ts(CmpOp, Time),
+    Category(StrTest),
+    Name(StrTest),
+    Pid(CmpOp, u64),
+    Tid(CmpOp, u64),
+    Arg(ArgTest),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ordering.is_eq(),
+            CmpOp::Ne => ordering.is_ne(),
+            CmpOp::Lt => ordering.is_lt(),
+            CmpOp::Le => ordering.is_le(),
+            CmpOp::Gt => ordering.is_gt(),
+            CmpOp::Ge => ordering.is_ge(),
+        }
+    }
+
+    fn token(self) -> &'static str {
+        match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        }
+    }
+}
+
+impl From<CmpOp> for ArgOp {
+    fn from(op: CmpOp) -> Self {
+        match op {
+            CmpOp::Eq => ArgOp::Eq,
+            CmpOp::Ne => ArgOp::Ne,
+            CmpOp::Lt => ArgOp::Lt,
+            CmpOp::Le => ArgOp::Le,
+            CmpOp::Gt => ArgOp::Gt,
+            CmpOp::Ge => ArgOp::Ge,
+        }
+    }
+}
+
+/// A window bound in an expression, and its value in ticks once resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Time {
+    pub bound: Bound,
+    pub ticks: Option<u64>,
+}
+
+impl Time {
+    /// A time as written, already in ticks if it is a plain tick count.
+    pub fn new(bound: Bound) -> Self {
+        Self {
+            bound,
+            ticks: bound.ticks(),
+        }
+    }
+
+    fn ticks(&self) -> Result<u64> {
+        self.ticks.ok_or_else(|| {
+            anyhow!("`ts` is compared with a time that has not been converted to ticks yet")
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum StrTest {
+    Eq(String),
+    Ne(String),
+    Regex(Regex),
+}
+
+impl StrTest {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            StrTest::Eq(value) => text == value,
+            StrTest::Ne(value) => text != value,
+            StrTest::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ArgTest {
+    Compare(ArgPredicate),
+    /// The argument's name and a pattern its string value must match.
+    Regex(String, Regex),
+}
+
+impl ArgTest {
+    fn matches(&self, name: &str, value: ArgValue) -> bool {
+        match self {
+            ArgTest::Compare(predicate) => name == predicate.name && predicate.matches(value),
+            ArgTest::Regex(arg, regex) => {
+                name == arg && matches!(value, ArgValue::Str(text) if regex.is_match(text))
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Whether the event passes. Fails if the event's fields cannot be resolved, or if a
+    /// time the event is compared with has not been resolved.
+    pub fn matches<E: EventFields + ?Sized>(&self, event: &E) -> Result<bool> {
+        match self {
+            Expr::And(left, right) => Ok(left.matches(event)? && right.matches(event)?),
+            Expr::Or(left, right) => Ok(left.matches(event)? || right.matches(event)?),
+            Expr::Not(inner) => Ok(!inner.matches(event)?),
+            Expr::Test(test) => test.matches(event),
+        }
+    }
+
+    /// Whether some time in the expression needs the first and last event timestamps.
+    pub fn is_relative(&self) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => left.is_relative() || right.is_relative(),
+            Expr::Not(inner) => inner.is_relative(),
+            Expr::Test(Test::TsIn(start, end)) => start.bound.is_relative() || end.bound.is_relative(),
+            Expr::Test(Test::Ts(_, time)) => time.bound.is_relative(),
+            Expr::Test(_) => false,
+        }
+    }
+
+    /// Converts every time not yet in ticks using `clock`. Times that `clock` cannot
+    /// resolve stay pending, and the first such failure is returned.
+    pub fn resolve(&mut self, clock: &TraceClock) -> Result<()> {
+        let mut result = Ok(());
+        self.for_each_time(&mut |time| {
+            if time.ticks.is_none() {
+                match time.bound.to_ticks(clock) {
+                    Ok(ticks) => time.ticks = Some(ticks),
+                    Err(e) => {
+                        if result.is_ok() {
+                            result = Err(e);
+                        }
+                    }
+                }
+            }
+        });
+        result
+    }
+
+    fn for_each_time(&mut self, f: &mut dyn FnMut(&mut Time)) {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.for_each_time(f);
+                right.for_each_time(f);
+            }
+            Expr::Not(inner) => inner.for_each_time(f),
+            Expr::Test(Test::TsIn(start, end)) => {
+                f(start);
+                f(end);
+            }
+            Expr::Test(Test::Ts(_, time)) => f(time),
+            Expr::Test(_) => {}
+        }
+    }
+}
+
+impl Test {
+    fn matches<E: EventFields + ?Sized>(&self, event: &E) -> Result<bool> {
+        match self {
+            Test::TsIn(start, end) => {
+                let ts = event.timestamp();
+                Ok(ts >= start.ticks()? && ts <= end.ticks()?)
+            }
+            Test::Ts(op, time) => Ok(op.holds(event.timestamp().cmp(&time.ticks()?))),
+            Test::Category(test) => Ok(test.matches(event.category()?)),
+            Test::Name(test) => Ok(test.matches(event.name()?)),
+            Test::Pid(op, koid) => Ok(op.holds(event.process_koid()?.cmp(koid))),
+            Test::Tid(op, koid) => Ok(op.holds(event.thread_koid()?.cmp(koid))),
+            Test::Arg(test) => event.any_arg(&mut |name, value| test.matches(name, value)),
+        }
+    }
+}
+
+/// Why an expression failed to parse, and at which character, counting from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, ParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            source,
+            tokens,
+            next: 0,
+        };
+        let expr = parser.or_expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(_) => Err(parser.error("Expected `and`, `or` or the end of the expression")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    Cmp(CmpOp),
+    /// `~`, matching against a regular expression.
+    Match,
+    Word(String),
+    Quoted(String),
+}
+
+/// Tokens with the byte offset they start at.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let error = |at: usize, message: &str| ParseError {
+        column: source[..at].chars().count() + 1,
+        message: message.to_string(),
+    };
+    let is_special = |c: char| c.is_whitespace() || "()\"=!<>~".contains(c);
+
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '~' => Token::Match,
+            '=' | '!' | '<' | '>' => {
+                let eq = chars.next_if(|&(_, next)| next == '=').is_some();
+                match (c, eq) {
+                    ('=', true) => Token::Cmp(CmpOp::Eq),
+                    ('!', true) => Token::Cmp(CmpOp::Ne),
+                    ('<', eq) => Token::Cmp(if eq { CmpOp::Le } else { CmpOp::Lt }),
+                    ('>', eq) => Token::Cmp(if eq { CmpOp::Ge } else { CmpOp::Gt }),
+                    ('=', _) => return Err(error(at, "Expected `==`")),
+                    _ => return Err(error(at, "Expected `!=`, or `not` to negate a test")),
+                }
+            }
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped @ ('"' | '\\'))) => text.push(escaped),
+                            _ => return Err(error(at, "Unknown escape in the string starting")),
+                        },
+                        Some((_, c)) => text.push(c),
+                        None => return Err(error(at, "Unterminated string starting")),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some((_, next)) = chars.next_if(|&(_, next)| !is_special(next)) {
+                    word.push(next);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push((at, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).map(|(_, token)| token.clone());
+        self.next += 1;
+        token
+    }
+
+    fn eat_word(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(word)) if word == keyword);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    /// An error at the next token, or at the end of the source if there is none.
+    fn error(&self, message: &str) -> ParseError {
+        self.error_at(self.next, message)
+    }
+
+    fn error_at(&self, token: usize, message: &str) -> ParseError {
+        let at = self.tokens.get(token).map_or(self.source.len(), |(at, _)| *at);
+        ParseError {
+            column: self.source[..at].chars().count() + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and_expr()?;
+        while self.eat_word("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        while self.eat_word("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat_word("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.next += 1;
+            let expr = self.or_expr()?;
+            if self.advance() != Some(Token::RParen) {
+                return Err(self.error_at(self.next - 1, "Expected `)`"));
+            }
+            return Ok(expr);
+        }
+        self.test().map(Expr::Test)
+    }
+
+    fn test(&mut self) -> Result<Test, ParseError> {
+        let field_at = self.next;
+        let Some(Token::Word(field)) = self.advance() else {
+            return Err(self.error_at(field_at, "Expected a field, `not` or `(`"));
+        };
+
+        if field == "ts" && self.eat_word("in") {
+            let (value_at, value) = self.value()?;
+            let window = value
+                .parse::<Window>()
+                .map_err(|e| self.error_at(value_at, &e.to_string()))?;
+            return Ok(Test::TsIn(Time::new(window.start), Time::new(window.end)));
+        }
+
+        let op_at = self.next;
+        let op = match self.advance() {
+            Some(Token::Cmp(op)) => Some(op),
+            Some(Token::Match) => None,
+            _ => return Err(self.error_at(op_at, "Expected a comparison operator")),
+        };
+        let (value_at, value) = self.value()?;
+        let parse_error = |e: &dyn fmt::Display| self.error_at(value_at, &e.to_string());
+        let regex = || Regex::new(&value).map_err(|e| parse_error(&e));
+        let koid = || value.parse::<u64>().map_err(|_| parse_error(&"Expected a koid"));
+        let numeric_only = || {
+            let message = format!("`{field}` is compared as a number, so `~` does not apply");
+            self.error_at(op_at, &message)
+        };
+        let str_test = || match op {
+            Some(CmpOp::Eq) => Ok(StrTest::Eq(value.clone())),
+            Some(CmpOp::Ne) => Ok(StrTest::Ne(value.clone())),
+            Some(op) => Err(self.error_at(
+                op_at,
+                &format!("`{field}` is a string, so only `==`, `!=` and `~` apply, not `{}`", op.token()),
+            )),
+            None => regex().map(StrTest::Regex),
+        };
+
+        match field.as_str() {
+            "ts" => {
+                let op = op.ok_or_else(numeric_only)?;
+                let bound = value.parse::<Bound>().map_err(|e| parse_error(&e))?;
+                Ok(Test::Ts(op, Time::new(bound)))
+            }
+            "cat" => str_test().map(Test::Category),
+            "name" => str_test().map(Test::Name),
+            "pid" => Ok(Test::Pid(op.ok_or_else(numeric_only)?, koid()?)),
+            "tid" => Ok(Test::Tid(op.ok_or_else(numeric_only)?, koid()?)),
+            _ => {
+                let Some(name) = field.strip_prefix("arg.").filter(|name| !name.is_empty()) else {
+                    return Err(self.error_at(
+                        field_at,
+                        &format!("Unknown field `{field}`, expected ts, cat, name, pid, tid or arg.NAME"),
+                    ));
+                };
+                match op {
+                    Some(op) => ArgPredicate::new(name, op.into(), &value)
+                        .map(|predicate| Test::Arg(ArgTest::Compare(predicate)))
+                        .map_err(|e| parse_error(&e)),
+                    None => Ok(Test::Arg(ArgTest::Regex(name.to_string(), regex()?))),
+                }
+            }
+        }
+    }
+
+    /// A bare word or quoted string, with the index of its token.
+    fn value(&mut self) -> Result<(usize, String), ParseError> {
+        let at = self.next;
+        match self.advance() {
+            Some(Token::Word(value) | Token::Quoted(value)) => Ok((at, value)),
+            _ => Err(self.error_at(at, "Expected a value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestEvent {
+        ts: u64,
+        category: &'static str,
+        name: &'static str,
+        pid: u64,
+        tid: u64,
+        args: Vec<(&'static str, ArgValue<'static>)>,
+    }
+
+    impl EventFields for TestEvent {
+        fn timestamp(&self) -> u64 {
+            self.ts
+        }
+
+        fn category(&self) -> Result<&str> {
+            Ok(self.category)
+        }
+
+        fn name(&self) -> Result<&str> {
+            Ok(self.name)
+        }
+
+        fn process_koid(&self) -> Result<u64> {
+            Ok(self.pid)
+        }
+
+        fn thread_koid(&self) -> Result<u64> {
+            Ok(self.tid)
+        }
+
+        fn any_arg(&self, f: &mut dyn FnMut(&str, ArgValue) -> bool) -> Result<bool> {
+            Ok(self.args.iter().any(|&(name, value)| f(name, value)))
+        }
+    }
+
+    fn vsync() -> TestEvent {
+        TestEvent {
+            ts: 1_500,
+            category: "gfx",
+            name: "VsyncCallback",
+            pid: 4,
+            tid: 40,
+            args: vec![("frame_id", ArgValue::Int(1234)), ("url", ArgValue::Str("/checkout"))],
+        }
+    }
+
+    fn matches(source: &str, event: &TestEvent) -> bool {
+        let mut expr: Expr = source.parse().unwrap();
+        let clock = TraceClock {
+            ticks_per_second: Some(1_000),
+            span: Some((1_000, 3_000)),
+        };
+        expr.resolve(&clock).unwrap();
+        expr.matches(event).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let event = vsync();
+        assert!(matches(r#"ts in 1s..2s and (cat == "gfx" or name ~ "Vsync.*") and pid != 5"#, &event));
+        assert!(!matches("ts in 1.6s..2s", &event));
+        assert!(matches("ts >= +500ms and ts < 50%", &event));
+        assert!(matches("cat == input or name ~ ^Vsync", &event));
+        assert!(!matches("not name ~ Vsync", &event));
+        assert!(matches("tid == 40 and not (pid > 4)", &event));
+        assert!(matches("arg.frame_id >= 1000 and arg.url ~ check", &event));
+        assert!(!matches("arg.missing == 1", &event));
+        assert!(matches(r#"arg.url == "/checkout""#, &event));
+        // `and` binds tighter than `or`
+        assert!(matches("pid == 4 or pid == 5 and tid == 0", &event));
+        assert!(!matches("(pid == 4 or pid == 5) and tid == 0", &event));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut expr: Expr = "ts > 5 or ts < 1s".parse().unwrap();
+        assert!(!expr.is_relative());
+        // Tick counts need nothing from the trace, so an event passing that comparison
+        // needs no tick rate
+        assert!(expr.matches(&vsync()).unwrap());
+        assert!(expr.resolve(&TraceClock::default()).is_err());
+
+        let mut expr: Expr = "ts < 1s".parse().unwrap();
+        assert!(expr.matches(&vsync()).is_err(), "Times in units wait for the tick rate");
+        let clock = TraceClock {
+            ticks_per_second: Some(1_000),
+            span: None,
+        };
+        expr.resolve(&clock).unwrap();
+        assert!(!expr.matches(&vsync()).unwrap());
+
+        let expr: Expr = "ts in -1s..end".parse().unwrap();
+        assert!(expr.is_relative());
+        assert!(expr.matches(&vsync()).is_err(), "Unresolved times cannot be compared");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| source.parse::<Expr>().unwrap_err();
+        assert_eq!(error("pid = 5").column, 5);
+        assert_eq!(error("cat == gfx and").column, 15);
+        assert_eq!(error("(pid == 4").column, 10);
+        assert_eq!(error("pid == 4)").column, 9);
+        assert_eq!(error("size > 3").column, 1);
+        assert_eq!(error("pid == x").column, 8);
+        assert_eq!(error("cat < gfx").column, 5);
+        assert_eq!(error("name ~ \"(\"").column, 8);
+        assert_eq!(error("ts in 1s").column, 7);
+        assert_eq!(error("cat == \"gfx").column, 8);
+        assert_eq!(error("é and pid == 1").to_string(), "Expected a comparison operator at column 3");
+    }
+}
@@ -0,0 +1,362 @@
+//! Which events to keep besides the time window, decided from their resolved fields.
+
+use crate::expr::Expr;
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
+use std::{cmp::Ordering, convert::Infallible, str::FromStr};
+
+/// Include and exclude lists of koids. An empty include list includes every koid.
+#[derive(Clone, Debug, Default)]
+pub struct KoidFilter {
+    pub include: Vec<u64>,
+    pub exclude: Vec<u64>,
+}
+
+impl KoidFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, koid: u64) -> bool {
+        (self.include.is_empty() || self.include.contains(&koid)) && !self.exclude.contains(&koid)
+    }
+}
+
+/// A shell-style pattern: `*` matches any run of characters and `?` any one character.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+}
+
+impl Glob {
+    pub fn matches(&self, text: &str) -> bool {
+        let pattern = self.pattern.as_str();
+        let (mut p, mut t) = (0, 0);
+        // Where to resume after the last `*` if the rest fails to match: the pattern
+        // just past it, and the text it should then swallow one more character of
+        let mut star = None;
+        while let Some(text_char) = text[t..].chars().next() {
+            match pattern[p..].chars().next() {
+                Some('*') => {
+                    p += 1;
+                    star = Some((p, t));
+                }
+                Some(c) if c == '?' || c == text_char => {
+                    p += c.len_utf8();
+                    t += text_char.len_utf8();
+                }
+                _ => match star {
+                    Some((star_p, star_t)) => {
+                        let swallowed = text[star_t..].chars().next().map_or(0, char::len_utf8);
+                        p = star_p;
+                        t = star_t + swallowed;
+                        star = Some((star_p, t));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].chars().all(|c| c == '*')
+    }
+}
+
+impl FromStr for Glob {
+    type Err = Infallible;
+
+    fn from_str(pattern: &str) -> Result<Self, Infallible> {
+        Ok(Self {
+            pattern: pattern.to_string(),
+        })
+    }
+}
+
+/// Include and exclude lists of glob patterns. An empty include list includes every
+/// string.
+#[derive(Clone, Debug, Default)]
+pub struct GlobFilter {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+}
+
+impl GlobFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.matches(text)))
+            && !self.exclude.iter().any(|glob| glob.matches(text))
+    }
+}
+
+/// Include and exclude lists of regular expressions, each matching anywhere in the
+/// string unless anchored. An empty include list includes every string.
+#[derive(Clone, Debug, Default)]
+pub struct RegexFilter {
+    pub include: Vec<Regex>,
+    pub exclude: Vec<Regex>,
+}
+
+impl RegexFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|regex| regex.is_match(text)))
+            && !self.exclude.iter().any(|regex| regex.is_match(text))
+    }
+}
+
+/// The value of an event argument, with string references already resolved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgValue<'a> {
+    Int(i128),
+    Float(f64),
+    Str(&'a str),
+}
+
+impl ArgValue<'_> {
+    fn compare(self, number: ArgValue) -> Option<Ordering> {
+        match (self, number) {
+            (ArgValue::Int(a), ArgValue::Int(b)) => Some(a.cmp(&b)),
+            (ArgValue::Int(a), ArgValue::Float(b)) => (a as f64).partial_cmp(&b),
+            (ArgValue::Float(a), ArgValue::Int(b)) => a.partial_cmp(&(b as f64)),
+            (ArgValue::Float(a), ArgValue::Float(b)) => a.partial_cmp(&b),
+            _ => None,
+        }
+    }
+}
+
+/// How an argument predicate compares an argument with its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// `*=`, a string argument containing the operand.
+    Contains,
+}
+
+/// Longer operators first, so `<=` is not read as `<`.
+const ARG_OPS: [(&str, ArgOp); 7] = [
+    ("==", ArgOp::Eq),
+    ("!=", ArgOp::Ne),
+    ("<=", ArgOp::Le),
+    (">=", ArgOp::Ge),
+    ("*=", ArgOp::Contains),
+    ("<", ArgOp::Lt),
+    (">", ArgOp::Gt),
+];
+
+/// A test on a named event argument, such as `frame_id==1234`, `latency>=2.5` or
+/// `url*=checkout`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArgPredicate {
+    pub name: String,
+    op: ArgOp,
+    text: String,
+    /// The operand as a number, if it is one.
+    number: Option<ArgValue<'static>>,
+}
+
+impl ArgPredicate {
+    /// Builds a predicate from its parts. Ordering operators need a numeric operand.
+    pub fn new(name: &str, op: ArgOp, text: &str) -> Result<Self> {
+        if name.is_empty() {
+            return Err(anyhow!("Missing argument name"));
+        }
+        let number = match text.parse::<i128>() {
+            Ok(int) => Some(ArgValue::Int(int)),
+            Err(_) => text.parse::<f64>().ok().map(ArgValue::Float),
+        };
+        if number.is_none() && !matches!(op, ArgOp::Eq | ArgOp::Ne | ArgOp::Contains) {
+            return Err(anyhow!("`{text}` is not a number"));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            op,
+            text: text.to_string(),
+            number,
+        })
+    }
+
+    /// Whether an argument with this predicate's name and the given value passes.
+    /// Strings are only tested for equality and substrings, and numbers compare
+    /// numerically. A string never equals a number.
+    pub fn matches(&self, value: ArgValue) -> bool {
+        if self.op == ArgOp::Contains {
+            return matches!(value, ArgValue::Str(text) if text.contains(self.text.as_str()));
+        }
+        let ordering = match value {
+            ArgValue::Str(text) if matches!(self.op, ArgOp::Eq | ArgOp::Ne) => {
+                Some(text.cmp(self.text.as_str()))
+            }
+            ArgValue::Str(_) => None,
+            number => self.number.and_then(|operand| number.compare(operand)),
+        };
+        match (self.op, ordering) {
+            (ArgOp::Ne, ordering) => ordering != Some(Ordering::Equal),
+            (_, None) => false,
+            (ArgOp::Eq, Some(ordering)) => ordering.is_eq(),
+            (ArgOp::Lt, Some(ordering)) => ordering.is_lt(),
+            (ArgOp::Le, Some(ordering)) => ordering.is_le(),
+            (ArgOp::Gt, Some(ordering)) => ordering.is_gt(),
+            (ArgOp::Ge, Some(ordering)) => ordering.is_ge(),
+            (ArgOp::Contains, _) => unreachable!(),
+        }
+    }
+}
+
+impl FromStr for ArgPredicate {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let found = value.char_indices().find_map(|(at, _)| {
+            ARG_OPS
+                .iter()
+                .find(|(token, _)| value[at..].starts_with(token))
+                .map(|&(token, op)| (at, token, op))
+        });
+        let Some((at, token, op)) = found else {
+            return Err(anyhow!(
+                "Expected `<name><op><value>` with one of ==, !=, <, <=, >, >= or *=, got `{value}`"
+            ));
+        };
+        Self::new(&value[..at], op, &value[at + token.len()..])
+            .with_context(|| format!("Invalid argument predicate `{value}`"))
+    }
+}
+
+/// A family of event records. Begin, end and complete records of durations count as
+/// durations, and likewise for async and flow events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Duration,
+    Counter,
+    Instant,
+    Async,
+    Flow,
+}
+
+const EVENT_KINDS: [(&str, EventKind); 5] = [
+    ("duration", EventKind::Duration),
+    ("counter", EventKind::Counter),
+    ("instant", EventKind::Instant),
+    ("async", EventKind::Async),
+    ("flow", EventKind::Flow),
+];
+
+impl FromStr for EventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        EVENT_KINDS
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|&(_, kind)| kind)
+            .ok_or_else(|| anyhow!("Unknown event kind `{value}`, expected duration, counter, instant, async or flow"))
+    }
+}
+
+/// Filters applied to every event on top of the time window.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    /// The kinds of event to keep, or every kind if empty.
+    pub kinds: Vec<EventKind>,
+    pub pids: KoidFilter,
+    pub tids: KoidFilter,
+    pub categories: GlobFilter,
+    pub names: RegexFilter,
+    /// Predicates that must all pass, each on some argument of the event.
+    pub args: Vec<ArgPredicate>,
+    /// An expression the event must pass, checked after everything else.
+    pub condition: Option<Expr>,
+}
+
+impl EventFilter {
+    pub fn keeps_kind(&self, kind: EventKind) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&kind)
+    }
+
+    /// Whether the filter needs the event's process and thread koids.
+    pub fn uses_thread(&self) -> bool {
+        !self.pids.is_empty() || !self.tids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        pattern.parse().unwrap()
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob("gfx*").matches("gfx"));
+        assert!(glob("gfx*").matches("gfx:present"));
+        assert!(!glob("gfx*").matches("input:gfx"));
+        assert!(glob("*:sched").matches("kernel:sched"));
+        assert!(glob("k*l:*").matches("kernel:irq"));
+        assert!(glob("a?c").matches("abc"));
+        assert!(glob("a?c").matches("aéc"));
+        assert!(!glob("a?c").matches("ac"));
+        assert!(glob("*a*b").matches("xaxxab"));
+        assert!(!glob("*a*b").matches("xaxxa"));
+        assert!(glob("*").matches(""));
+    }
+
+    #[test]
+    fn test_arg_predicates() {
+        let predicate = |text: &str| text.parse::<ArgPredicate>().unwrap();
+        assert_eq!(predicate("frame_id==1234").name, "frame_id");
+        assert_eq!(ArgPredicate::new("latency", ArgOp::Ge, "2.5").unwrap(), predicate("latency>=2.5"));
+        assert!(predicate("frame_id==1234").matches(ArgValue::Int(1234)));
+        assert!(predicate("frame_id==1234").matches(ArgValue::Float(1234.0)));
+        assert!(!predicate("frame_id==1234").matches(ArgValue::Str("abc")));
+        assert!(predicate("frame_id!=1234").matches(ArgValue::Str("abc")));
+        assert!(predicate("latency>=2.5").matches(ArgValue::Int(3)));
+        assert!(!predicate("latency<2.5").matches(ArgValue::Float(2.5)));
+        assert!(predicate("big>18446744073709551614").matches(ArgValue::Int(u64::MAX as i128)));
+        assert!(predicate("url*=checkout").matches(ArgValue::Str("/cart/checkout?id=1")));
+        assert!(!predicate("url*=checkout").matches(ArgValue::Str("/cart")));
+        assert!(predicate("phase==draw").matches(ArgValue::Str("draw")));
+        assert!(predicate("a==b<c").matches(ArgValue::Str("b<c")), "The first operator splits");
+        assert!(!predicate("latency>2").matches(ArgValue::Str("3")));
+
+        assert!("frame_id".parse::<ArgPredicate>().is_err(), "Missing operator");
+        assert!("==1".parse::<ArgPredicate>().is_err(), "Missing name");
+        assert!("latency>slow".parse::<ArgPredicate>().is_err(), "Ordering needs a number");
+    }
+
+    #[test]
+    fn test_event_kinds() {
+        assert_eq!("flow".parse::<EventKind>().unwrap(), EventKind::Flow);
+        assert!("slice".parse::<EventKind>().is_err());
+        let filter = EventFilter {
+            kinds: vec![EventKind::Duration, EventKind::Counter],
+            ..EventFilter::default()
+        };
+        assert!(filter.keeps_kind(EventKind::Counter));
+        assert!(!filter.keeps_kind(EventKind::Flow));
+        assert!(EventFilter::default().keeps_kind(EventKind::Flow));
+    }
+
+    #[test]
+    fn test_glob_filter() {
+        let filter = GlobFilter {
+            include: vec![glob("gfx*"), glob("kernel:*")],
+            exclude: vec![glob("kernel:sched")],
+        };
+        assert!(filter.matches("gfx"));
+        assert!(filter.matches("kernel:irq"));
+        assert!(!filter.matches("kernel:sched"));
+        assert!(!filter.matches("input"));
+        assert!(GlobFilter::default().matches("anything"));
+    }
+}
@@ -0,0 +1,7 @@
+//! The parts of `ftf-cut` that do not depend on the trace reader: window bounds, event
+//! filters and the `--where` expression language, for deciding what to keep from other
+//! tools as well.
+
+pub mod expr;
+pub mod filter;
+pub mod time;
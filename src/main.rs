@@ -1,160 +1,1746 @@
-use anyhow::{Ok, Result, anyhow};
-use ftfrs::{Event, EventRecord, Record, RecordHeader, RecordType, StringRecord, StringRef};
+use anyhow::{Context, Ok, Result, anyhow};
+use ftfrs::{
+    Argument, Event, EventRecord, Record, RecordHeader, RecordType, StringRecord, StringRef, ThreadRecord,
+    ThreadRef,
+};
 use std::{
+    fmt,
     fs::File,
-    io::{BufWriter, Cursor, ErrorKind, Read, Seek, Write},
-    path::PathBuf, 
+    io::{BufWriter, Cursor, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
 use rustc_hash::{FxHashMap, FxHashSet};
 
-use clap::Parser;
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+
+mod wire;
+
+use ftf_cut::{
+    expr::{EventFields, Expr},
+    filter::{ArgPredicate, ArgValue, EventFilter, EventKind, Glob, GlobFilter, KoidFilter, RegexFilter},
+    time::{Bound, Timestamp, TraceClock, Window},
+};
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    #[arg(short, long)]
-    start_ts: u64,
-    #[arg(short, long)]
-    end_ts: u64,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Arguments of a plain cut, present whenever there is no subcommand
+    #[command(flatten)]
+    cut: Option<CutArgs>,
+}
+
+#[derive(Args)]
+struct CutArgs {
+    /// Start of the window, in ticks or with a unit: 1.25s, 300ms, 42us, 17ns. Also
+    /// +OFFSET from the first event, -OFFSET from the last, a percentage, start or end
+    #[arg(
+        short,
+        long,
+        visible_alias = "start-ts",
+        value_name = "TIME",
+        allow_hyphen_values = true,
+        required_unless_present_any = ["range", "ranges_file"]
+    )]
+    start: Option<Bound>,
+    /// End of the window, in the same forms as --start
+    #[arg(
+        short,
+        long,
+        visible_alias = "end-ts",
+        value_name = "TIME",
+        allow_hyphen_values = true,
+        required_unless_present_any = ["range", "ranges_file"]
+    )]
+    end: Option<Bound>,
+    /// Both ends of the window as START..END, e.g. +500ms..+700ms, -2s..end or 40%..45%.
+    /// Repeat to cut several windows in one pass
+    #[arg(
+        short,
+        long,
+        value_name = "RANGE",
+        allow_hyphen_values = true,
+        conflicts_with_all = ["start", "end"]
+    )]
+    range: Vec<Window>,
+    /// File with one START..END range per line, cut alongside any --range windows.
+    /// Blank lines and lines starting with # are ignored
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["start", "end"])]
+    ranges_file: Option<PathBuf>,
+    #[arg(short, long, value_name = "FILE")]
+    input_path: PathBuf,
+    /// Output file. With several windows, each is written next to it with its number
+    /// before the extension: cut-0.fxt, cut-1.fxt, ...
+    #[arg(short, long, value_name = "FILE")]
+    output_path: PathBuf,
+    /// Close duration slices that cross the window bounds with synthetic begin/end records
+    #[arg(long)]
+    balance_slices: bool,
+    /// Which DurationComplete slices to keep when they straddle the window
+    #[arg(long, value_enum, default_value_t = OverlapPolicy::Start)]
+    overlap_policy: OverlapPolicy,
+    /// Start each counter track with its last value from before the window. Only numeric
+    /// arguments are carried over
+    #[arg(long)]
+    seed_counters: bool,
+    /// Skip corrupt records instead of aborting, resuming at the next plausible record,
+    /// and drop records that refer to unbound string or thread indexes
+    #[arg(long)]
+    lenient: bool,
+    /// Only keep kernel object records for processes and threads the kept records refer to
+    #[arg(long)]
+    prune_kernel_objects: bool,
+    /// Subtract OFFSET, or the window start if none is given, from every timestamp written
+    #[arg(long, value_name = "OFFSET", num_args = 0..=1)]
+    rebase: Option<Option<Timestamp>>,
+    /// Only keep these kinds of event: duration, counter, instant, async or flow
+    #[arg(long, value_name = "KIND", value_delimiter = ',')]
+    kinds: Vec<EventKind>,
+    /// Only keep events from these process koids
+    #[arg(long, value_name = "KOID", value_delimiter = ',')]
+    pid: Vec<u64>,
+    /// Drop events from these process koids
+    #[arg(long, value_name = "KOID", value_delimiter = ',')]
+    exclude_pid: Vec<u64>,
+    /// Only keep events from these thread koids
+    #[arg(long, value_name = "KOID", value_delimiter = ',')]
+    tid: Vec<u64>,
+    /// Drop events from these thread koids
+    #[arg(long, value_name = "KOID", value_delimiter = ',')]
+    exclude_tid: Vec<u64>,
+    /// Only keep events whose category matches one of these patterns, e.g. 'gfx*'
+    #[arg(long, value_name = "GLOB")]
+    category: Vec<Glob>,
+    /// Drop events whose category matches one of these patterns, e.g. 'kernel:*'
+    #[arg(long, value_name = "GLOB")]
+    exclude_category: Vec<Glob>,
+    /// Only keep events whose name matches one of these regular expressions
+    #[arg(long, value_name = "REGEX")]
+    name_regex: Vec<Regex>,
+    /// Drop events whose name matches one of these regular expressions
+    #[arg(long, value_name = "REGEX")]
+    exclude_name_regex: Vec<Regex>,
+    /// Only keep events with an argument passing this test, e.g. 'frame_id==1234' or
+    /// 'url*=checkout'. Repeated predicates must all pass
+    #[arg(long = "arg", value_name = "PREDICATE")]
+    args: Vec<ArgPredicate>,
+    /// Only keep events passing this expression, e.g.
+    /// 'ts in 1s..2s and (cat == "gfx" or name ~ "Vsync.*") and pid != 5'
+    #[arg(long = "where", value_name = "EXPR")]
+    condition: Option<Expr>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Split the whole trace into numbered chunks that can each be read on their own
+    Split(SplitArgs),
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("limit").args(["every", "max_bytes"]).required(true).multiple(true)))]
+struct SplitArgs {
     #[arg(short, long, value_name = "FILE")]
     input_path: PathBuf,
+    /// Chunks are written next to this path with their number before the extension:
+    /// trace-0.fxt, trace-1.fxt, ...
     #[arg(short, long, value_name = "FILE")]
     output_path: PathBuf,
+    /// Start a new chunk every TIME of trace time, in ticks or with a unit: 1s, 300ms
+    #[arg(long, value_name = "TIME")]
+    every: Option<Timestamp>,
+    /// Start a new chunk before one would grow past SIZE, in bytes or with a unit:
+    /// 256MiB, 1GB
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_bytes: Option<u64>,
+    /// Skip corrupt records instead of aborting, resuming at the next plausible record,
+    /// and drop records that refer to unbound string or thread indexes
+    #[arg(long)]
+    lenient: bool,
 }
-fn main() -> Result<()> {
+
+/// Exit status for input that ends part-way through a record, distinct from clap's usage
+/// error status `2`.
+const TRUNCATED_EXIT_STATUS: u8 = 3;
+
+fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
-    let file = File::open(cli.input_path)?;
+    match (cli.command, cli.cut) {
+        (Some(Command::Split(args)), _) => split(args),
+        (None, Some(args)) => cut(args),
+        (None, None) => {
+            Cli::command().print_help()?;
+            Ok(ExitCode::from(2))
+        }
+    }
+}
+
+fn cut(cli: CutArgs) -> Result<ExitCode> {
+    let mut windows = cli.range;
+    if let Some(path) = &cli.ranges_file {
+        windows.extend(read_ranges(path)?);
+    }
+    if let (Some(start), Some(end)) = (cli.start, cli.end) {
+        windows.push(Window { start, end });
+    }
+    if windows.is_empty() {
+        return Err(anyhow!("No windows to cut"));
+    }
+
+    let file = File::open(&cli.input_path)?;
     let map = unsafe { memmap2::Mmap::map(&file)? };
-    let output = BufWriter::new(File::create(cli.output_path)?);
-    let mut cutter = Cutter::new(Cursor::new(map), output, cli.start_ts, cli.end_ts);
+    let output_path = cli.output_path;
+    let outputs = windows
+        .iter()
+        .enumerate()
+        .map(|(index, &window)| {
+            let path = match windows.len() {
+                1 => output_path.clone(),
+                _ => numbered_output_path(&output_path, index),
+            };
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            Ok((BufWriter::new(file), window))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let options = CutOptions {
+        balance_slices: cli.balance_slices,
+        overlap_policy: cli.overlap_policy,
+        seed_counters: cli.seed_counters,
+        lenient: cli.lenient,
+        prune_kernel_objects: cli.prune_kernel_objects,
+        rebase: match cli.rebase {
+            None => Rebase::Off,
+            Some(None) => Rebase::WindowStart,
+            Some(Some(offset)) => Rebase::By(offset),
+        },
+        filter: EventFilter {
+            kinds: cli.kinds,
+            pids: KoidFilter {
+                include: cli.pid,
+                exclude: cli.exclude_pid,
+            },
+            tids: KoidFilter {
+                include: cli.tid,
+                exclude: cli.exclude_tid,
+            },
+            categories: GlobFilter {
+                include: cli.category,
+                exclude: cli.exclude_category,
+            },
+            names: RegexFilter {
+                include: cli.name_regex,
+                exclude: cli.exclude_name_regex,
+            },
+            args: cli.args,
+            condition: cli.condition,
+        },
+    };
+    let mut cutter = Cutter::with_windows(Cursor::new(map), outputs, options);
     println!("Cutting");
-    cutter.cut()?;
-    println!("Done");
-    Ok(())
+    report(cutter.cut()?)
 }
 
-struct Cutter<R: Read + Seek, W: Write> {
-    input: R,
-    output: W,
-    index_to_offset: FxHashMap<u16, Record>,
-    written_indexes: FxHashSet<u16>,
+fn split(args: SplitArgs) -> Result<ExitCode> {
+    let file = File::open(&args.input_path)?;
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    let limits = SplitLimits {
+        every: args.every,
+        max_bytes: args.max_bytes,
+    };
+    let output_path = args.output_path;
+    let next_output = Box::new(move |index: usize| {
+        let path = numbered_output_path(&output_path, index);
+        println!("Writing {}", path.display());
+        let file = File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(BufWriter::new(file))
+    });
+    let options = CutOptions {
+        lenient: args.lenient,
+        ..CutOptions::default()
+    };
+    let mut cutter = Cutter::splitting(Cursor::new(map), limits, next_output, options)?;
+    report(cutter.cut()?)
+}
+
+/// Prints how the cut went and picks the exit status.
+fn report(summary: CutSummary) -> Result<ExitCode> {
+    if !summary.skipped.is_empty() {
+        let skipped_bytes: u64 = summary.skipped.iter().map(|(start, end)| end - start).sum();
+        eprintln!(
+            "Skipped {skipped_bytes} bytes in {} corrupt ranges:",
+            summary.skipped.len()
+        );
+        for (start, end) in &summary.skipped {
+            eprintln!("  {start}..{end} ({} bytes)", end - start);
+        }
+    }
+    if !summary.unresolved.is_empty() {
+        eprintln!(
+            "Dropped {} records referring to unbound string or thread indexes, at byte offsets:",
+            summary.unresolved.len()
+        );
+        for offset in &summary.unresolved {
+            eprintln!("  {offset}");
+        }
+    }
+    match summary.outcome {
+        CutOutcome::Complete => {
+            println!("Done ({} records)", summary.records);
+            Ok(ExitCode::SUCCESS)
+        }
+        CutOutcome::Truncated { offset } => {
+            eprintln!(
+                "Input truncated mid-record at byte offset {offset} after {} records",
+                summary.records
+            );
+            Ok(ExitCode::from(TRUNCATED_EXIT_STATUS))
+        }
+    }
+}
+
+/// Reads `START..END` ranges from a file, one per line.
+fn read_ranges(path: &Path) -> Result<Vec<Window>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read ranges from {}", path.display()))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse::<Window>()
+                .with_context(|| format!("Invalid range `{line}` in {}", path.display()))
+        })
+        .collect()
+}
+
+/// `path` with `index` inserted before its extension.
+fn numbered_output_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{index}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{index}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Parses a size in bytes, with an optional decimal (KB, MB, GB) or binary (KiB, MiB,
+/// GiB) unit.
+fn parse_size(value: &str) -> Result<u64> {
+    const UNITS: [(&str, u64); 6] = [
+        ("KB", 1_000),
+        ("MB", 1_000_000),
+        ("GB", 1_000_000_000),
+        ("KiB", 1 << 10),
+        ("MiB", 1 << 20),
+        ("GiB", 1 << 30),
+    ];
+    let unit_start = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid size `{value}`"))?;
+    let scale = match unit {
+        "" | "B" => 1,
+        _ => match UNITS.iter().find(|(name, _)| *name == unit) {
+            Some(&(_, scale)) => scale,
+            None => return Err(anyhow!("Unknown size unit `{unit}` in `{value}`")),
+        },
+    };
+    number
+        .checked_mul(scale)
+        .ok_or_else(|| anyhow!("Size `{value}` is too large"))
+}
+
+/// Returns the common event fields shared by every event record variant.
+fn event_of(record: &EventRecord) -> &Event {
+    match record {
+        EventRecord::Instant(i) => i.event(),
+        EventRecord::Counter(c) => c.event(),
+        EventRecord::DurationBegin(d) => d.event(),
+        EventRecord::DurationEnd(d) => d.event(),
+        EventRecord::DurationComplete(d) => d.event(),
+        EventRecord::AsyncBegin(a) => a.event(),
+        EventRecord::AsyncInstant(a) => a.event(),
+        EventRecord::AsyncEnd(a) => a.event(),
+        EventRecord::FlowBegin(f) => f.event(),
+        EventRecord::FlowStep(f) => f.event(),
+        EventRecord::FlowEnd(f) => f.event(),
+    }
+}
+
+fn event_kind(record: &EventRecord) -> EventKind {
+    match record {
+        EventRecord::Instant(_) => EventKind::Instant,
+        EventRecord::Counter(_) => EventKind::Counter,
+        EventRecord::DurationBegin(_) | EventRecord::DurationEnd(_) | EventRecord::DurationComplete(_) => {
+            EventKind::Duration
+        }
+        EventRecord::AsyncBegin(_) | EventRecord::AsyncInstant(_) | EventRecord::AsyncEnd(_) => EventKind::Async,
+        EventRecord::FlowBegin(_) | EventRecord::FlowStep(_) | EventRecord::FlowEnd(_) => EventKind::Flow,
+    }
+}
+
+/// The string and thread records an event refers to by index.
+fn event_refs(event: &Event) -> wire::Refs {
+    let mut refs = wire::Refs::default();
+    if let ThreadRef::Ref(idx) = event.thread() {
+        refs.threads.push(*idx);
+    }
+    for string in [event.name(), event.category()] {
+        if let StringRef::Ref(idx) = string {
+            refs.strings.push(*idx);
+        }
+    }
+    for arg in event.arguments() {
+        if let StringRef::Ref(idx) = arg.name() {
+            refs.strings.push(*idx);
+        }
+        if let Argument::Str(_, StringRef::Ref(idx)) = arg {
+            refs.strings.push(*idx);
+        }
+    }
+    refs
+}
+
+/// How the input ended.
+#[derive(Debug, PartialEq, Eq)]
+enum CutOutcome {
+    /// Every record in the input was read.
+    Complete,
+    /// The input ended part-way through the record starting at `offset`.
+    Truncated { offset: u64 },
+}
+
+#[derive(Debug)]
+struct CutSummary {
+    outcome: CutOutcome,
+    /// Number of complete records processed.
+    records: u64,
+    /// Byte ranges `[start, end)` skipped as corrupt in lenient mode.
+    skipped: Vec<(u64, u64)>,
+    /// Offsets of the records dropped in lenient mode for referring to unbound indexes.
+    unresolved: Vec<u64>,
+}
+
+/// Context attached to errors from records that do not decode, as opposed to errors
+/// from reading, writing or resolving what a record refers to.
+#[derive(Debug)]
+struct DecodeError;
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Malformed record")
+    }
+}
+
+/// Whether an error from cutting a record comes from malformed input, which `--lenient`
+/// skips, rather than from anything that would fail the same way on the next record.
+fn is_corruption(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<DecodeError>().is_some()
+}
+
+/// Context attached to errors from records that refer to a string or thread index no
+/// earlier record has bound.
+#[derive(Debug)]
+struct UnresolvedReference;
+
+impl fmt::Display for UnresolvedReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Unresolved reference")
+    }
+}
+
+/// Whether an error from cutting a record comes from a reference `--lenient` drops the
+/// record for.
+fn is_unresolved(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<UnresolvedReference>().is_some()
+}
+
+/// Decodes a record ftfrs understands, checking that it spans exactly the bytes its
+/// header declares.
+fn decode_record(record: &[u8]) -> Result<Record> {
+    let mut reader = Cursor::new(record);
+    let decoded = Record::from_bytes(&mut reader).context(DecodeError)?;
+    if reader.position() != record.len() as u64 {
+        return Err(anyhow!(
+            "Record decoded to {} bytes but its header declares {}",
+            reader.position(),
+            record.len()
+        )
+        .context(DecodeError));
+    }
+    Ok(decoded)
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OverlapPolicy {
+    /// Keep slices that start inside the window
+    #[default]
+    Start,
+    /// Keep any slice that intersects the window
+    Overlap,
+    /// Keep intersecting slices, clipped to the window bounds
+    Clip,
+}
+
+/// What to subtract from the timestamps that are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Rebase {
+    #[default]
+    Off,
+    WindowStart,
+    By(Timestamp),
+}
+
+impl Rebase {
+    /// Ticks to subtract in a window starting at `start_ts`, if that does not depend on
+    /// the tick rate.
+    fn ticks(self, start_ts: u64) -> Option<u64> {
+        match self {
+            Rebase::Off => Some(0),
+            Rebase::WindowStart => Some(start_ts),
+            Rebase::By(offset) => offset.ticks(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct CutOptions {
+    /// Write synthetic `DurationBegin` records at `start_ts` and `DurationEnd` records at
+    /// `end_ts` for slices that are open when the window starts or ends.
+    balance_slices: bool,
+    overlap_policy: OverlapPolicy,
+    /// Write the last value of each counter seen before `start_ts` as a sample at `start_ts`.
+    /// The sample keeps only the counter's numeric arguments.
+    seed_counters: bool,
+    /// Skip records that fail to parse and resynchronize on the next plausible header,
+    /// and drop records that refer to unbound string or thread indexes.
+    lenient: bool,
+    /// Only write kernel object records whose koid is referenced by a kept record.
+    prune_kernel_objects: bool,
+    rebase: Rebase,
+    /// Filters events must pass on top of the time window.
+    filter: EventFilter,
+}
+
+/// A duration slice that has begun but not yet ended on some thread. Strings are
+/// stored inline so the slice can be re-emitted after its indexes have been rebound.
+struct OpenSlice {
+    category: StringRef,
+    name: StringRef,
+    /// Whether the event filters kept the begin, and so keep the end and any synthetic
+    /// records for the slice.
+    kept: bool,
+}
+
+/// The most recent pre-window sample of a counter, with its strings stored inline.
+struct CounterSample {
+    thread_koid: u64,
+    category: StringRef,
+    arguments: Vec<Argument>,
+}
+
+/// String and thread tables of a single trace provider. Indexes are scoped per provider,
+/// so each provider has its own bindings.
+#[derive(Default)]
+struct ProviderTables {
+    /// Latest binding of each string index, tagged with the generation it was bound in.
+    index_to_offset: FxHashMap<u16, (u64, Record)>,
+    /// Latest binding of each thread index, tagged like the string bindings.
+    index_to_thread: FxHashMap<u8, (u64, Record)>,
+    /// Whether the name patterns match each string index, tagged with the generation of
+    /// the binding that was matched.
+    matched_names: FxHashMap<u16, (u64, bool)>,
+}
+
+/// A kernel object record held back until a kept record refers to its koid.
+struct PendingObject {
+    record: Vec<u8>,
+    provider: u32,
+    /// Bindings of the strings the record references, as of when it was read.
+    strings: Vec<(u16, u64, Record)>,
+    /// Koids the record refers to, such as the process of a thread.
+    koids: Vec<u64>,
+}
+
+/// Passes writes through to `inner`, counting the bytes written.
+struct CountingWriter<W: Write> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner
+            .write(buf)
+            .inspect(|&written| self.written += written as u64)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A window being cut from the input, along with its output and everything written to
+/// that output so far.
+struct OutputWindow<W: Write> {
+    output: CountingWriter<W>,
     start_ts: u64,
     end_ts: u64,
+    /// Window still waiting for the tick rate or the trace's span to be converted to ticks.
+    pending: Option<Window>,
+    /// Ticks subtracted from every timestamp written.
+    rebase_by: u64,
+    /// Generation of the binding last written for each (provider, string index).
+    written_indexes: FxHashMap<(u32, u16), u64>,
+    /// Generation of the binding last written for each (provider, thread index).
+    written_threads: FxHashMap<(u32, u8), u64>,
+    /// Stacks of open slices keyed by (process koid, thread koid), innermost last.
+    open_slices: FxHashMap<(u64, u64), Vec<OpenSlice>>,
+    /// Last pre-window sample keyed by (process koid, counter name, counter id).
+    last_counters: FxHashMap<(u64, String, u64), CounterSample>,
+    entered_window: bool,
+    referenced_koids: FxHashSet<u64>,
+}
+
+impl<W: Write> OutputWindow<W> {
+    fn new(output: W, window: Window, rebase: Rebase) -> Self {
+        // Plain tick counts are known up front. Anything in time units stays pending until
+        // the initialization record gives the tick rate, and relative bounds until the scan
+        // at the start of the cut
+        let resolved = window
+            .ticks()
+            .and_then(|(start_ts, end_ts)| Some((start_ts, end_ts, rebase.ticks(start_ts)?)));
+        let (start_ts, end_ts, rebase_by) = resolved.unwrap_or((0, u64::MAX, 0));
+        Self {
+            output: CountingWriter { inner: output, written: 0 },
+            start_ts,
+            end_ts,
+            pending: resolved.is_none().then_some(window),
+            rebase_by,
+            written_indexes: FxHashMap::default(),
+            written_threads: FxHashMap::default(),
+            open_slices: FxHashMap::default(),
+            last_counters: FxHashMap::default(),
+            entered_window: false,
+            referenced_koids: FxHashSet::default(),
+        }
+    }
+
+    /// Converts the window and rebase offset to ticks, failing if `clock` lacks something
+    /// they depend on.
+    fn resolve(&mut self, clock: &TraceClock, rebase: Rebase) -> Result<()> {
+        let Some(window) = self.pending else {
+            return Ok(());
+        };
+        let (start_ts, end_ts) = window.to_ticks(clock)?;
+        self.rebase_by = match rebase {
+            Rebase::Off => 0,
+            Rebase::WindowStart => start_ts,
+            Rebase::By(offset) => clock.ticks(offset)?,
+        };
+        (self.start_ts, self.end_ts) = (start_ts, end_ts);
+        self.pending = None;
+        Ok(())
+    }
+}
+
+/// When `ftf-cut split` starts a new chunk. Either limit or both may be set.
+#[derive(Clone, Copy, Default)]
+struct SplitLimits {
+    /// Length of each chunk in trace time.
+    every: Option<Timestamp>,
+    /// Size a chunk is not allowed to grow past, unless a single record takes it there.
+    max_bytes: Option<u64>,
+}
+
+/// Rotation of the output into chunks that can each be read on their own.
+struct Split<W> {
+    limits: SplitLimits,
+    /// Opens the output for the chunk with the given number.
+    next_output: Box<dyn FnMut(usize) -> Result<W>>,
+    chunks: usize,
+    /// Start of the next time chunk, set by the first timestamped record.
+    next_boundary: Option<u64>,
+    /// Records every chunk after the first starts with: the magic number,
+    /// initialization and provider info records, in input order.
+    preamble: Vec<Vec<u8>>,
+    /// Provider current at the end of the preamble.
+    preamble_provider: u32,
+    /// Bytes in the current chunk before its first record of its own.
+    chunk_start: u64,
+}
+
+struct Cutter<R: Read + Seek, W: Write> {
+    input: R,
+    /// Tables of the provider whose section is currently being read.
+    tables: ProviderTables,
+    provider: u32,
+    /// Tables of every other provider seen so far, swapped in when their section resumes.
+    other_providers: FxHashMap<u32, ProviderTables>,
+    generation: u64,
+    options: CutOptions,
+    /// Latest kernel object record for each koid that some window has held back.
+    pending_objects: FxHashMap<u64, PendingObject>,
+    /// The window records are currently being cut for.
+    window: OutputWindow<W>,
+    /// Every other window, swapped into `window` in turn for each record.
+    other_windows: Vec<OutputWindow<W>>,
+    split: Option<Split<W>>,
 }
 
 impl<R: Read + Seek, W: Write> Cutter<R, W> {
     fn new(input: R, output: W, start_ts: u64, end_ts: u64) -> Self {
-        let index_to_offset = FxHashMap::default();
-        let written_indexes = FxHashSet::default();
+        Self::with_options(input, output, start_ts, end_ts, CutOptions::default())
+    }
+
+    fn with_options(input: R, output: W, start_ts: u64, end_ts: u64, options: CutOptions) -> Self {
+        Self::with_window(input, output, Window::from_ticks(start_ts, end_ts), options)
+    }
+
+    fn with_window(input: R, output: W, window: Window, options: CutOptions) -> Self {
+        Self::with_windows(input, vec![(output, window)], options)
+    }
+
+    /// Cuts every window in a single pass over the input, each to its own output.
+    /// `windows` must not be empty.
+    fn with_windows(input: R, windows: Vec<(W, Window)>, options: CutOptions) -> Self {
+        let rebase = options.rebase;
+        let mut windows = windows
+            .into_iter()
+            .map(|(output, window)| OutputWindow::new(output, window, rebase));
+        let window = windows.next().expect("at least one window to cut");
         Self {
             input,
-            output,
-            index_to_offset,
-            written_indexes,
-            start_ts,
-            end_ts,
+            tables: ProviderTables::default(),
+            provider: 0,
+            other_providers: FxHashMap::default(),
+            generation: 0,
+            options,
+            pending_objects: FxHashMap::default(),
+            window,
+            other_windows: windows.collect(),
+            split: None,
+        }
+    }
+
+    /// Copies the whole input to a series of chunks, each written to the output
+    /// `next_output` opens for its number.
+    fn splitting(
+        input: R,
+        limits: SplitLimits,
+        mut next_output: Box<dyn FnMut(usize) -> Result<W>>,
+        options: CutOptions,
+    ) -> Result<Self> {
+        if limits.every.is_some_and(|every| every.ticks() == Some(0)) {
+            return Err(anyhow!("Chunks cannot be zero ticks long"));
+        }
+        // Chunk sizes only count the records read from the input, so nothing may write
+        // records of its own or hold them back
+        if options.balance_slices || options.seed_counters || options.prune_kernel_objects {
+            return Err(anyhow!(
+                "Splitting does not balance slices, seed counters or prune kernel objects"
+            ));
+        }
+        let output = next_output(0)?;
+        let mut cutter = Self::with_window(input, output, Window::from_ticks(0, u64::MAX), options);
+        cutter.split = Some(Split {
+            limits,
+            next_output,
+            chunks: 1,
+            next_boundary: None,
+            preamble: Vec::new(),
+            preamble_provider: 0,
+            chunk_start: 0,
+        });
+        Ok(cutter)
+    }
+
+    /// Runs `f` with each window in turn as the current one.
+    fn for_each_window(&mut self, mut f: impl FnMut(&mut Self) -> Result<()>) -> Result<()> {
+        f(self)?;
+        for i in 0..self.other_windows.len() {
+            std::mem::swap(&mut self.window, &mut self.other_windows[i]);
+            let result = f(self);
+            std::mem::swap(&mut self.window, &mut self.other_windows[i]);
+            result?;
         }
+        Ok(())
     }
 
-    fn cut(&mut self) -> Result<()> {
+    fn cut(&mut self) -> Result<CutSummary> {
         let mut header_buf = [0_u8; 8];
+        let mut records = 0;
+        let mut skipped = Vec::new();
+        let mut unresolved = Vec::new();
 
-        loop {
-            if let Err(e) = self.input.read_exact(&mut header_buf) {
-                if e.kind() == ErrorKind::UnexpectedEof {
-                    break;
-                }
+        let input_start = self.input.stream_position()?;
+        let input_len = self.input.seek(SeekFrom::End(0))?;
+        let needs_scan = std::iter::once(&self.window)
+            .chain(&self.other_windows)
+            .any(|window| window.pending.is_some_and(|pending| pending.is_relative()))
+            || self.options.filter.condition.as_ref().is_some_and(Expr::is_relative);
+        if needs_scan {
+            let clock = self.scan_clock(input_start, input_len)?;
+            self.resolve_windows(&clock)?;
+        }
+        self.input.seek(SeekFrom::Start(input_start))?;
+
+        let outcome = loop {
+            let offset = self.input.stream_position()?;
+            let read = self.read_header(&mut header_buf).with_context(|| {
+                format!("Failed to read record header at byte offset {offset} after {records} records")
+            })?;
+            if read == 0 {
+                break CutOutcome::Complete;
+            }
+            if read < header_buf.len() {
+                break CutOutcome::Truncated { offset };
             }
 
             let header = RecordHeader {
                 value: u64::from_ne_bytes(header_buf),
             };
-            let record_type = header.record_type()?;
-            match record_type {
-                RecordType::String => {
-                    self.input.seek_relative(-8)?;
-                    let event = Record::from_bytes(&mut self.input)?;
-                    self.index_to_offset.insert(StringRecord::index_from_header(&header), event);
+            if offset + wire::record_len(header.value) > input_len {
+                // A corrupt size looks just like a truncated record, unless a plausible
+                // record follows it
+                let resume = if self.options.lenient {
+                    self.resync(offset + 8, input_len)?
+                } else {
+                    input_len
+                };
+                if resume == input_len {
+                    break CutOutcome::Truncated { offset };
                 }
-                RecordType::Event => {
-                    self.input.seek_relative(-8)?;
-                    let event = Record::from_bytes(&mut self.input)?;
-                    if let Record::Event(e) = &event {
-                        let write_it = match e {
-                            EventRecord::DurationBegin(d) => self.process_event(d.event())?,
-                            EventRecord::DurationEnd(d) => self.process_event(d.event())?,
-                            EventRecord::DurationComplete(d) => self.process_event(d.event())?,
-                            EventRecord::Counter(c) => self.process_event(c.event())?,
-                            EventRecord::Instant(i) => self.process_event(i.event())?,
-                            _ => true,
-                        };
-
-                        if write_it { 
-                            event.write(&mut self.output)?;
-                        }
-                    }
+                skipped.push((offset, resume));
+                self.input.seek(SeekFrom::Start(resume))?;
+                continue;
+            }
+
+            if let Err(e) = self.cut_record(&header, &header_buf) {
+                // The whole record has been read, so the next one follows
+                if self.options.lenient && is_unresolved(&e) {
+                    unresolved.push(offset);
+                    continue;
                 }
-                _ => {
-                    self.output.write_all(&header_buf)?;
-                    if header.size() > 1 {
-                        let mut rest = vec![0_u8; (header.size() as usize - 1) * 8];
-                        self.input.read_exact(&mut rest)?;
-                        self.output.write_all(&rest)?;
-                    }
+                if !(self.options.lenient && is_corruption(&e)) {
+                    return Err(e.context(format!(
+                        "Failed to cut record at byte offset {offset} after {records} records"
+                    )));
+                }
+                let resume = self.resync(offset + 8, input_len)?;
+                skipped.push((offset, resume));
+                self.input.seek(SeekFrom::Start(resume))?;
+                continue;
+            }
+            records += 1;
+        };
+
+        self.for_each_window(|cutter| {
+            cutter.close_window()?;
+            cutter.window.output.flush()?;
+            Ok(())
+        })?;
+        Ok(CutSummary {
+            outcome,
+            records,
+            skipped,
+            unresolved,
+        })
+    }
+
+    /// Finds the tick rate and the earliest and latest event timestamps by hopping from
+    /// header to header, without decoding anything else. Stops early at a corrupt size.
+    fn scan_clock(&mut self, from: u64, input_len: u64) -> Result<TraceClock> {
+        let mut clock = TraceClock::default();
+        let mut offset = from;
+        let mut buf = [0_u8; 16];
+        while offset + 16 <= input_len {
+            self.input.seek(SeekFrom::Start(offset))?;
+            self.input.read_exact(&mut buf)?;
+            let words = wire::to_words(&buf);
+            if let Some(ts) = wire::event_timestamp(&words) {
+                clock.span = Some(match clock.span {
+                    Some((first, last)) => (first.min(ts), last.max(ts)),
+                    None => (ts, ts),
+                });
+            } else if let Some(ticks_per_second) = wire::ticks_per_second(&words) {
+                clock.ticks_per_second.get_or_insert(ticks_per_second);
+            }
+
+            let len = wire::record_len(words[0]);
+            if len == 0 {
+                break;
+            }
+            offset += len;
+        }
+        Ok(clock)
+    }
+
+    /// Scans forward word by word from `from` for the next plausible record header,
+    /// returning its offset or `input_len` if there is none.
+    fn resync(&mut self, from: u64, input_len: u64) -> Result<u64> {
+        let mut offset = from;
+        while offset + 8 <= input_len {
+            // Requiring the following header to be plausible as well keeps payload words
+            // that happen to look like a header from derailing the cut
+            if let Some(next) = self.plausible_record_end(offset, input_len)? {
+                if next == input_len || self.plausible_record_end(next, input_len)?.is_some() {
+                    return Ok(offset);
                 }
             }
-            // break;
+            offset += 8;
+        }
+        Ok(input_len)
+    }
+
+    /// Returns where the record at `offset` ends if its header looks valid.
+    fn plausible_record_end(&mut self, offset: u64, input_len: u64) -> Result<Option<u64>> {
+        let mut word = [0_u8; 8];
+        self.input.seek(SeekFrom::Start(offset))?;
+        self.input.read_exact(&mut word)?;
+        let header = RecordHeader {
+            value: u64::from_ne_bytes(word),
+        };
+
+        let len = wire::record_len(header.value);
+        let end = offset + len;
+        if header.record_type().is_err() || len == 0 || end > input_len {
+            return Ok(None);
+        }
+        Ok(Some(end))
+    }
+
+    /// Fills `buf` from the input, returning fewer bytes than requested only at EOF.
+    fn read_header(&mut self, buf: &mut [u8; 8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.input.read(&mut buf[filled..]) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => match result? {
+                    0 => break,
+                    n => filled += n,
+                },
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Cuts the record whose header has just been read. The whole record is read and
+    /// decoded before anything is written or bound, so a record `--lenient` skips leaves
+    /// nothing behind in the outputs or the tables.
+    fn cut_record(&mut self, header: &RecordHeader, header_buf: &[u8; 8]) -> Result<()> {
+        let record_len = wire::record_len(header.value);
+        if record_len == 0 {
+            return Err(anyhow!("Record header declares a zero length").context(DecodeError));
+        }
+        let record = self.read_record(header_buf, record_len)?;
+        if header.value & 0xf == wire::LARGE_RECORD_TYPE {
+            self.cut_opaque_record(&record)
+        } else {
+            self.cut_standard_record(header, record)
+        }
+    }
+
+    fn read_record(&mut self, header_buf: &[u8; 8], record_len: u64) -> Result<Vec<u8>> {
+        let mut record = vec![0_u8; record_len as usize];
+        record[..8].copy_from_slice(header_buf);
+        self.input.read_exact(&mut record[8..])?;
+        Ok(record)
+    }
+
+    /// In lenient mode, checks that every string and thread index in `refs` is bound before
+    /// anything is written for the record, so a dropped record leaves nothing behind.
+    /// Strict cuts only fail on the references they go on to resolve.
+    fn check_refs(&self, refs: &wire::Refs) -> Result<()> {
+        if !self.options.lenient {
+            return Ok(());
+        }
+        if let Some(idx) = refs.strings.iter().find(|idx| !self.tables.index_to_offset.contains_key(*idx)) {
+            return Err(anyhow!("Referenced String index missing: {idx}").context(UnresolvedReference));
+        }
+        if let Some(idx) = refs.threads.iter().find(|idx| !self.tables.index_to_thread.contains_key(*idx)) {
+            return Err(anyhow!("Referenced Thread index missing: {idx}").context(UnresolvedReference));
         }
         Ok(())
     }
 
-    fn maybe_write_str_ref(&mut self, idx: u16) -> Result<()> {
-        if self.written_indexes.contains(&idx) {
+    /// Cuts a record that ftfrs does not decode. Records placed outside the window are
+    /// dropped, and the ones kept are preceded by the records they reference. Records
+    /// that reference nothing are copied unchanged, and records that cannot be decoded
+    /// are malformed.
+    fn cut_opaque_record(&mut self, record: &[u8]) -> Result<()> {
+        let decoded = wire::decode(&wire::to_words(record)).context(DecodeError)?;
+        if let Some(decoded) = &decoded {
+            self.check_refs(&decoded.refs)?;
+        }
+        self.for_each_window(|cutter| cutter.cut_opaque_window(record, decoded.as_ref()))
+    }
+
+    /// Cuts an opaque record for the current window.
+    fn cut_opaque_window(&mut self, record: &[u8], decoded: Option<&wire::Decoded>) -> Result<()> {
+        let Some(decoded) = decoded else {
+            self.split_at_size(record.len() as u64, &wire::Refs::default())?;
+            self.window.output.write_all(record)?;
             return Ok(());
+        };
+        if let Some(ts) = decoded.timestamp {
+            self.check_window_resolved()?;
+            if !self.in_window(ts) {
+                return Ok(());
+            }
+            self.split_at_time(ts)?;
+        }
+        if let Some(koid) = decoded.object_koid {
+            if self.options.prune_kernel_objects && !self.window.referenced_koids.contains(&koid) {
+                return self.hold_kernel_object(koid, record, &decoded.refs);
+            }
         }
-        if let Some(rec) = self.index_to_offset.get(&idx) {
-            rec.write(&mut self.output)?;
+        self.split_at_size(record.len() as u64, &decoded.refs)?;
+        self.write_refs(&decoded.refs)?;
+
+        if decoded.timestamp.is_some() && self.window.rebase_by != 0 {
+            let mut rebased = record.to_vec();
+            wire::rebase_word(&mut rebased, decoded.timestamp_word, self.window.rebase_by);
+            self.window.output.write_all(&rebased)?;
         } else {
-            return Err(anyhow!("Referenced String index missing: {idx}"));
+            self.window.output.write_all(record)?;
         }
-        self.written_indexes.insert(idx);
         Ok(())
     }
 
-    fn process_event(&mut self, event: &Event) -> Result<bool> {
-        let ts = event.timestamp();
-        if ts < self.start_ts || ts > self.end_ts {
-            return Ok(false);
+    /// Resolves the windows, rebase offsets and the times in the `--where` expression.
+    fn resolve_windows(&mut self, clock: &TraceClock) -> Result<()> {
+        let rebase = self.options.rebase;
+        self.for_each_window(|cutter| cutter.window.resolve(clock, rebase))?;
+        if let Some(condition) = &mut self.options.filter.condition {
+            condition.resolve(clock)?;
         }
-        if let StringRef::Ref(idx) = event.name() {
-            self.maybe_write_str_ref(*idx)?
+        Ok(())
+    }
+
+    fn check_window_resolved(&self) -> Result<()> {
+        if self.window.pending.is_some() {
+            return Err(anyhow!(
+                "Window or rebase offset is given in time units, but no initialization record with the tick rate precedes the first timestamped record"
+            ));
         }
+        Ok(())
+    }
 
-        if let StringRef::Ref(idx) = event.category() {
-            self.maybe_write_str_ref(*idx)?
+    /// Holds back a kernel object record that nothing kept in the current window has
+    /// referred to yet, along with the current bindings of the strings it needs.
+    fn hold_kernel_object(&mut self, koid: u64, record: &[u8], refs: &wire::Refs) -> Result<()> {
+        let strings = refs
+            .strings
+            .iter()
+            .map(|idx| match self.tables.index_to_offset.get(idx) {
+                Some((generation, rec)) => Ok((*idx, *generation, rec.clone())),
+                None => Err(anyhow!("Referenced String index missing: {idx}")),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let object = PendingObject {
+            record: record.to_vec(),
+            provider: self.provider,
+            strings,
+            koids: refs.koids.clone(),
+        };
+        self.pending_objects.insert(koid, object);
+        Ok(())
+    }
+
+    /// Marks `koid` as referenced by a record kept in the current window, writing its
+    /// held back kernel object record first if there is one.
+    fn reference_koid(&mut self, koid: u64) -> Result<()> {
+        if !self.options.prune_kernel_objects || !self.window.referenced_koids.insert(koid) {
+            return Ok(());
         }
+        // Other windows may still need the record, so it goes back once written
+        let Some(object) = self.pending_objects.remove(&koid) else {
+            return Ok(());
+        };
+        let result = self.write_held_object(&object);
+        self.pending_objects.insert(koid, object);
+        result
+    }
 
-        for arg in event.arguments() {
-            let name_ref = arg.name();
-            if let StringRef::Ref(idx) = name_ref {
-                self.maybe_write_str_ref(*idx)?
+    fn write_held_object(&mut self, object: &PendingObject) -> Result<()> {
+        // Objects this one refers to, like a thread's process, go out first
+        for &koid in &object.koids {
+            self.reference_koid(koid)?;
+        }
+
+        // The record may come from another provider's section, whose string table it uses
+        let other_section = object.provider != self.provider;
+        if other_section {
+            self.write_provider_section(object.provider)?;
+        }
+        for (idx, generation, rec) in &object.strings {
+            let key = (object.provider, *idx);
+            if self.window.written_indexes.get(&key) != Some(generation) {
+                rec.write(&mut self.window.output)?;
+                self.window.written_indexes.insert(key, *generation);
             }
-            if let ftfrs::Argument::Str(_, StringRef::Ref(idx)) = arg {
-                self.maybe_write_str_ref(*idx)?
+        }
+        self.window.output.write_all(&object.record)?;
+        if other_section {
+            self.write_provider_section(self.provider)?;
+        }
+        Ok(())
+    }
+
+    fn cut_standard_record(&mut self, header: &RecordHeader, record: Vec<u8>) -> Result<()> {
+        match header.record_type().context(DecodeError)? {
+            RecordType::String => {
+                let event = decode_record(&record)?;
+                // A later string record with the same index rebinds it, so every
+                // binding gets a fresh generation and is re-emitted when referenced.
+                self.generation += 1;
+                self.tables.index_to_offset.insert(
+                    StringRecord::index_from_header(header),
+                    (self.generation, event),
+                );
+            }
+            RecordType::Thread => {
+                let thread = decode_record(&record)?;
+                self.generation += 1;
+                self.tables.index_to_thread.insert(
+                    ThreadRecord::index_from_header(header),
+                    (self.generation, thread),
+                );
+            }
+            RecordType::Event => {
+                let event = decode_record(&record)?;
+                if let Record::Event(e) = &event {
+                    self.check_refs(&event_refs(event_of(e)))?;
+                }
+                let kept = self.keeps_event(&event)?;
+                // Balancing slices follows every begin and end, including the dropped ones
+                if kept || self.options.balance_slices {
+                    let record_len = record.len() as u64;
+                    self.for_each_window(|cutter| cutter.cut_event(&event, record_len, kept))?;
+                }
+            }
+            RecordType::Initialization => {
+                if let Some(ticks_per_second) = wire::ticks_per_second(&wire::to_words(&record)) {
+                    self.resolve_windows(&TraceClock {
+                        ticks_per_second: Some(ticks_per_second),
+                        span: None,
+                    })?;
+                    if let Some(split) = &mut self.split {
+                        if let Some(every) = split.limits.every {
+                            split.limits.every = Some(Timestamp::Ticks(every.to_ticks(ticks_per_second)?));
+                        }
+                    }
+                }
+                self.for_each_window(|cutter| {
+                    cutter.split_at_size(record.len() as u64, &wire::Refs::default())?;
+                    Ok(cutter.window.output.write_all(&record)?)
+                })?;
+                // Only chunks started after this record repeat it
+                if let Some(split) = &mut self.split {
+                    split.preamble.push(record);
+                }
+            }
+            RecordType::Metadata => {
+                // Written before its section takes effect, so a chunk started ahead of
+                // it switches from the provider the previous records belong to
+                self.cut_opaque_record(&record)?;
+                if let Some(provider) = wire::provider_switch(header.value) {
+                    self.switch_provider(provider);
+                }
+                if let Some(split) = &mut self.split {
+                    if wire::is_trace_wide_metadata(header.value) {
+                        split.preamble.push(record);
+                    }
+                    if let Some(provider) = wire::provider_info(header.value) {
+                        split.preamble_provider = provider;
+                    }
+                }
             }
+            _ => self.cut_opaque_record(&record)?,
         }
+        Ok(())
+    }
 
-        Ok(true)
+    /// Starts a new chunk before a record at `ts` that lies past the current time chunk.
+    /// Time chunks that no record falls in are skipped rather than written empty.
+    fn split_at_time(&mut self, ts: u64) -> Result<()> {
+        let Some(split) = &mut self.split else {
+            return Ok(());
+        };
+        let every = match split.limits.every {
+            None => return Ok(()),
+            Some(Timestamp::Ticks(every)) => every.max(1),
+            Some(Timestamp::Nanos(_)) => {
+                return Err(anyhow!(
+                    "Chunk length is given in time units, but no initialization record with the tick rate precedes the first timestamped record"
+                ));
+            }
+        };
+        let boundary = *split.next_boundary.get_or_insert(ts.saturating_add(every));
+        if ts < boundary {
+            return Ok(());
+        }
+        let skipped = (ts - boundary) / every;
+        split.next_boundary = Some(boundary.saturating_add(skipped.saturating_add(1).saturating_mul(every)));
+        self.start_chunk()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
-    use ftfrs::{Argument, ThreadRef};
+    /// Starts a new chunk before a record of `record_len` bytes that would take the
+    /// current one past its size limit, along with the string and thread records in
+    /// `refs` that have to be written ahead of it. A chunk always gets at least one record.
+    fn split_at_size(&mut self, record_len: u64, refs: &wire::Refs) -> Result<()> {
+        let Some(split) = &self.split else {
+            return Ok(());
+        };
+        let Some(max_bytes) = split.limits.max_bytes else {
+            return Ok(());
+        };
+        let written = self.window.output.written;
+        if written > split.chunk_start && written + record_len + self.unwritten_refs_len(refs)? > max_bytes {
+            self.start_chunk()?;
+        }
+        Ok(())
+    }
 
-    // Helper to create test FTF data
+    /// Bytes of the string and thread records in `refs` that have not been written to the
+    /// current window yet, counting each once.
+    fn unwritten_refs_len(&self, refs: &wire::Refs) -> Result<u64> {
+        let mut sink = CountingWriter {
+            inner: std::io::sink(),
+            written: 0,
+        };
+        let mut threads = refs.threads.clone();
+        threads.sort_unstable();
+        threads.dedup();
+        for idx in threads {
+            let Some((generation, rec)) = self.tables.index_to_thread.get(&idx) else {
+                return Err(anyhow!("Referenced Thread index missing: {idx}"));
+            };
+            if self.window.written_threads.get(&(self.provider, idx)) != Some(generation) {
+                rec.write(&mut sink)?;
+            }
+        }
+        let mut strings = refs.strings.clone();
+        strings.sort_unstable();
+        strings.dedup();
+        for idx in strings {
+            let Some((generation, rec)) = self.tables.index_to_offset.get(&idx) else {
+                return Err(anyhow!("Referenced String index missing: {idx}"));
+            };
+            if self.window.written_indexes.get(&(self.provider, idx)) != Some(generation) {
+                rec.write(&mut sink)?;
+            }
+        }
+        Ok(sink.written)
+    }
+
+    /// Finishes the current chunk and opens the next. The new chunk starts with the
+    /// preamble, and since nothing else has been written to it yet, the string and
+    /// thread records its own records reference are written again as they come up.
+    fn start_chunk(&mut self) -> Result<()> {
+        self.close_window()?;
+        self.window.output.flush()?;
+
+        let Some(split) = &mut self.split else {
+            return Ok(());
+        };
+        let output = (split.next_output)(split.chunks)?;
+        split.chunks += 1;
+        self.window = OutputWindow::new(output, Window::from_ticks(0, u64::MAX), Rebase::Off);
+        for record in &split.preamble {
+            self.window.output.write_all(record)?;
+        }
+        if split.preamble_provider != self.provider {
+            self.write_provider_section(self.provider)?;
+        }
+        if let Some(split) = &mut self.split {
+            split.chunk_start = self.window.output.written;
+        }
+        Ok(())
+    }
+
+    /// Makes `provider`'s tables current. Metadata records are copied to every output as
+    /// they are read, so the outputs switch providers at the same point as the input.
+    fn switch_provider(&mut self, provider: u32) {
+        if provider == self.provider {
+            return;
+        }
+        let next = self.other_providers.remove(&provider).unwrap_or_default();
+        let previous = std::mem::replace(&mut self.tables, next);
+        self.other_providers.insert(self.provider, previous);
+        self.provider = provider;
+    }
+
+    /// Writes a provider section record so the current output switches to `provider`
+    /// mid-stream.
+    fn write_provider_section(&mut self, provider: u32) -> Result<()> {
+        let header = wire::provider_section_header(provider);
+        self.window.output.write_all(&header.to_ne_bytes())?;
+        Ok(())
+    }
+
+    /// Applies the filters other than the time window. Dropped events never reach any
+    /// window, so they neither pull in the strings they reference nor open slices.
+    fn keeps_event(&mut self, record: &Record) -> Result<bool> {
+        let Record::Event(e) = record else {
+            return Ok(true);
+        };
+        let event = event_of(e);
+        let filter = &self.options.filter;
+        if !filter.keeps_kind(event_kind(e)) {
+            return Ok(false);
+        }
+        if filter.uses_thread() {
+            let (process_koid, thread_koid) = self.thread_koids(event.thread())?;
+            if !filter.pids.matches(process_koid) || !filter.tids.matches(thread_koid) {
+                return Ok(false);
+            }
+        }
+        if !filter.categories.is_empty() && !filter.categories.matches(self.str_value(event.category())?) {
+            return Ok(false);
+        }
+        if !filter.names.is_empty() && !self.name_matches(event.name())? {
+            return Ok(false);
+        }
+        for predicate in &self.options.filter.args {
+            if !self.any_arg(event, &mut |name, value| name == predicate.name && predicate.matches(value))? {
+                return Ok(false);
+            }
+        }
+        if let Some(condition) = &self.options.filter.condition {
+            if !condition.matches(&EventView { cutter: self, event })? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Calls `f` with the resolved name and value of each argument until it returns true.
+    fn any_arg(&self, event: &Event, f: &mut dyn FnMut(&str, ArgValue) -> bool) -> Result<bool> {
+        for arg in event.arguments() {
+            let name = self.str_value(arg.name())?;
+            let value = match arg {
+                Argument::Int32(_, v) => ArgValue::Int(*v as i128),
+                Argument::UInt32(_, v) => ArgValue::Int(*v as i128),
+                Argument::Int64(_, v) => ArgValue::Int(*v as i128),
+                Argument::UInt64(_, v) => ArgValue::Int(*v as i128),
+                Argument::Float(_, v) => ArgValue::Float(*v),
+                Argument::Str(_, value) => ArgValue::Str(self.str_value(value)?),
+                Argument::KernelObjectId(_, koid) => ArgValue::Int(*koid as i128),
+                _ => continue,
+            };
+            if f(name, value) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Matches an event name against the name patterns. Results for indexed names are
+    /// cached per binding, so each string record is matched at most once.
+    fn name_matches(&mut self, name: &StringRef) -> Result<bool> {
+        let names = &self.options.filter.names;
+        let idx = match name {
+            StringRef::Inline(value) => return Ok(names.matches(value)),
+            StringRef::Ref(idx) => idx,
+        };
+        let Some((generation, Record::String(rec))) = self.tables.index_to_offset.get(idx) else {
+            return Err(anyhow!("Referenced String index missing: {idx}"));
+        };
+        match self.tables.matched_names.get(idx) {
+            Some(&(matched_generation, matched)) if matched_generation == *generation => Ok(matched),
+            _ => {
+                let matched = names.matches(rec.value());
+                self.tables.matched_names.insert(*idx, (*generation, matched));
+                Ok(matched)
+            }
+        }
+    }
+
+    /// Cuts an event for the current window. `kept` is whether the event filters keep it,
+    /// which only events that may end a balanced slice are passed without.
+    fn cut_event(&mut self, record: &Record, record_len: u64, kept: bool) -> Result<()> {
+        let Record::Event(e) = record else {
+            return Ok(());
+        };
+        self.check_window_resolved()?;
+        let ts = event_of(e).timestamp();
+        self.split_at_time(ts)?;
+        // A split keeps every event, at its original size
+        if self.split.is_some() {
+            self.split_at_size(record_len, &event_refs(event_of(e)))?;
+        }
+        // Synthetic records for the window start go out before the first event at or after it
+        if !self.window.entered_window && ts >= self.window.start_ts {
+            self.enter_window()?;
+        }
+        let kept = if self.options.balance_slices {
+            self.track_slice(e, kept)?
+        } else {
+            kept
+        };
+        if !kept {
+            return Ok(());
+        }
+
+        match e {
+            EventRecord::DurationComplete(d) => {
+                let end_ts = d.end_ts();
+                match self.process_duration_complete(d.event(), end_ts)? {
+                    Some(bounds) if bounds == (ts, end_ts) => {
+                        Self::write_event(&mut self.window.output, record, self.window.rebase_by)?;
+                    }
+                    Some((clipped_ts, clipped_end_ts)) => {
+                        let inner = d.event();
+                        let clipped = Record::create_duration_complete_event(
+                            clipped_ts,
+                            inner.thread().clone(),
+                            inner.category().clone(),
+                            inner.name().clone(),
+                            inner.arguments().to_vec(),
+                            clipped_end_ts,
+                        );
+                        Self::write_event(&mut self.window.output, &clipped, self.window.rebase_by)?;
+                    }
+                    None => {}
+                }
+            }
+            EventRecord::Counter(c) if self.options.seed_counters && ts < self.window.start_ts => {
+                self.remember_counter(c.event(), c.counter_id())?;
+            }
+            _ => {
+                if self.process_event(event_of(e))? {
+                    Self::write_event(&mut self.window.output, record, self.window.rebase_by)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Maintains the per-thread slice stacks for events up to the end of the window, and
+    /// returns whether the event is kept. An end is kept along with its begin, whatever the
+    /// filters make of the end itself, so slices stay balanced.
+    fn track_slice(&mut self, record: &EventRecord, kept: bool) -> Result<bool> {
+        let event = event_of(record);
+        if event.timestamp() > self.window.end_ts {
+            return Ok(kept);
+        }
+
+        match record {
+            EventRecord::DurationBegin(_) => {
+                let thread = self.thread_koids(event.thread())?;
+                let slice = OpenSlice {
+                    category: self.inline_str(event.category())?,
+                    name: self.inline_str(event.name())?,
+                    kept,
+                };
+                self.window.open_slices.entry(thread).or_default().push(slice);
+            }
+            EventRecord::DurationEnd(_) => {
+                let thread = self.thread_koids(event.thread())?;
+                let begin = self.window.open_slices.get_mut(&thread).and_then(Vec::pop);
+                if let Some(slice) = begin {
+                    return Ok(slice.kept);
+                }
+            }
+            _ => {}
+        }
+        Ok(kept)
+    }
+
+    /// Keeps the latest pre-window sample of each counter so the window can start with it.
+    fn remember_counter(&mut self, event: &Event, counter_id: u64) -> Result<()> {
+        let (process_koid, thread_koid) = self.thread_koids(event.thread())?;
+        let name = self.str_value(event.name())?.to_string();
+        // Counter values are numeric, so only their names need resolving. Anything else is
+        // left out of the seeded sample, as documented on `--seed-counters`
+        let mut arguments = Vec::new();
+        for arg in event.arguments() {
+            let arg_name = self.inline_str(arg.name())?;
+            let arg = match arg {
+                Argument::Int32(_, v) => Argument::Int32(arg_name, *v),
+                Argument::UInt32(_, v) => Argument::UInt32(arg_name, *v),
+                Argument::Int64(_, v) => Argument::Int64(arg_name, *v),
+                Argument::UInt64(_, v) => Argument::UInt64(arg_name, *v),
+                Argument::Float(_, v) => Argument::Float(arg_name, *v),
+                _ => continue,
+            };
+            arguments.push(arg);
+        }
+
+        let sample = CounterSample {
+            thread_koid,
+            category: self.inline_str(event.category())?,
+            arguments,
+        };
+        self.window.last_counters.insert((process_koid, name, counter_id), sample);
+        Ok(())
+    }
+
+    fn enter_window(&mut self) -> Result<()> {
+        self.window.entered_window = true;
+        // Sorted so the synthetic records come out the same way on every run
+        let mut counters: Vec<_> = self.window.last_counters.drain().collect();
+        counters.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        for ((process_koid, name, counter_id), sample) in counters {
+            let counter = Record::create_counter_event(
+                self.window.start_ts,
+                ThreadRef::Inline { process_koid, thread_koid: sample.thread_koid },
+                sample.category,
+                StringRef::Inline(name),
+                sample.arguments,
+                counter_id,
+            );
+            Self::write_event(&mut self.window.output, &counter, self.window.rebase_by)?;
+        }
+        for (process_koid, thread_koid) in self.open_slice_threads() {
+            let stack = &self.window.open_slices[&(process_koid, thread_koid)];
+            for slice in stack.iter().filter(|slice| slice.kept) {
+                let begin = Record::create_duration_begin_event(
+                    self.window.start_ts,
+                    ThreadRef::Inline { process_koid, thread_koid },
+                    slice.category.clone(),
+                    slice.name.clone(),
+                    Vec::new(),
+                );
+                Self::write_event(&mut self.window.output, &begin, self.window.rebase_by)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn close_window(&mut self) -> Result<()> {
+        if !self.window.entered_window {
+            self.enter_window()?;
+        }
+        for (process_koid, thread_koid) in self.open_slice_threads() {
+            let stack = &self.window.open_slices[&(process_koid, thread_koid)];
+            for slice in stack.iter().rev().filter(|slice| slice.kept) {
+                let end = Record::create_duration_end_event(
+                    self.window.end_ts,
+                    ThreadRef::Inline { process_koid, thread_koid },
+                    slice.category.clone(),
+                    slice.name.clone(),
+                    Vec::new(),
+                );
+                Self::write_event(&mut self.window.output, &end, self.window.rebase_by)?;
+            }
+        }
+        self.window.open_slices.clear();
+        Ok(())
+    }
+
+    /// Threads with open slices as (process koid, thread koid), in sorted order.
+    fn open_slice_threads(&self) -> Vec<(u64, u64)> {
+        let mut threads: Vec<_> = self.window.open_slices.keys().copied().collect();
+        threads.sort_unstable();
+        threads
+    }
+
+    /// Writes an event record with its timestamps moved back by `rebase_by` ticks. Takes
+    /// the output rather than `self` so callers can hold borrows of the slice stacks.
+    fn write_event(output: &mut CountingWriter<W>, record: &Record, rebase_by: u64) -> Result<()> {
+        if rebase_by == 0 {
+            record.write(output)?;
+            return Ok(());
+        }
+        let mut bytes = Vec::new();
+        record.write(&mut bytes)?;
+        wire::rebase_event(&mut bytes, rebase_by);
+        output.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Resolves a thread reference to its (process koid, thread koid) pair.
+    fn thread_koids(&self, thread: &ThreadRef) -> Result<(u64, u64)> {
+        match thread {
+            ThreadRef::Inline { process_koid, thread_koid } => Ok((*process_koid, *thread_koid)),
+            ThreadRef::Ref(idx) => match self.tables.index_to_thread.get(idx) {
+                Some((_, Record::Thread(rec))) => Ok((rec.process_koid(), rec.thread_koid())),
+                _ => Err(anyhow!("Referenced Thread index missing: {idx}")),
+            },
+        }
+    }
+
+    /// Resolves an indexed string reference against the current binding of its index.
+    fn inline_str(&self, string: &StringRef) -> Result<StringRef> {
+        match string {
+            StringRef::Ref(_) => Ok(StringRef::Inline(self.str_value(string)?.to_string())),
+            inline => Ok(inline.clone()),
+        }
+    }
+
+    /// Returns the text of a string reference, inline or indexed.
+    fn str_value<'a>(&'a self, string: &'a StringRef) -> Result<&'a str> {
+        match string {
+            StringRef::Inline(value) => Ok(value),
+            StringRef::Ref(idx) => match self.tables.index_to_offset.get(idx) {
+                Some((_, Record::String(rec))) => Ok(rec.value()),
+                _ => Err(anyhow!("Referenced String index missing: {idx}")),
+            },
+        }
+    }
+
+    fn maybe_write_str_ref(&mut self, idx: u16) -> Result<()> {
+        let Some((generation, rec)) = self.tables.index_to_offset.get(&idx) else {
+            return Err(anyhow!("Referenced String index missing: {idx}"));
+        };
+        let key = (self.provider, idx);
+        if self.window.written_indexes.get(&key) == Some(generation) {
+            return Ok(());
+        }
+        rec.write(&mut self.window.output)?;
+        self.window.written_indexes.insert(key, *generation);
+        Ok(())
+    }
+
+    fn maybe_write_thread_ref(&mut self, idx: u8) -> Result<()> {
+        let Some((generation, rec)) = self.tables.index_to_thread.get(&idx) else {
+            return Err(anyhow!("Referenced Thread index missing: {idx}"));
+        };
+        let key = (self.provider, idx);
+        if self.window.written_threads.get(&key) == Some(generation) {
+            return Ok(());
+        }
+        rec.write(&mut self.window.output)?;
+        self.window.written_threads.insert(key, *generation);
+        Ok(())
+    }
+
+    fn in_window(&self, ts: u64) -> bool {
+        ts >= self.window.start_ts && ts <= self.window.end_ts
+    }
+
+    fn process_event(&mut self, event: &Event) -> Result<bool> {
+        if !self.in_window(event.timestamp()) {
+            return Ok(false);
+        }
+        self.write_event_refs(event)?;
+        Ok(true)
+    }
+
+    /// Decides whether a `DurationComplete` slice is kept under the configured overlap
+    /// policy, returning the start and end timestamps it should be written with.
+    fn process_duration_complete(&mut self, event: &Event, end_ts: u64) -> Result<Option<(u64, u64)>> {
+        let ts = event.timestamp();
+        let bounds = match self.options.overlap_policy {
+            OverlapPolicy::Start if ts >= self.window.start_ts && ts <= self.window.end_ts => (ts, end_ts),
+            OverlapPolicy::Overlap if ts <= self.window.end_ts && end_ts >= self.window.start_ts => (ts, end_ts),
+            OverlapPolicy::Clip if ts <= self.window.end_ts && end_ts >= self.window.start_ts => {
+                (ts.max(self.window.start_ts), end_ts.min(self.window.end_ts))
+            }
+            _ => return Ok(None),
+        };
+        self.write_event_refs(event)?;
+        Ok(Some(bounds))
+    }
+
+    fn write_event_refs(&mut self, event: &Event) -> Result<()> {
+        if self.options.prune_kernel_objects {
+            let (process_koid, thread_koid) = self.thread_koids(event.thread())?;
+            self.reference_koid(process_koid)?;
+            self.reference_koid(thread_koid)?;
+            for arg in event.arguments() {
+                if let Argument::KernelObjectId(_, koid) = arg {
+                    self.reference_koid(*koid)?;
+                }
+            }
+        }
+
+        if let ThreadRef::Ref(idx) = event.thread() {
+            self.maybe_write_thread_ref(*idx)?
+        }
+
+        if let StringRef::Ref(idx) = event.name() {
+            self.maybe_write_str_ref(*idx)?
+        }
+
+        if let StringRef::Ref(idx) = event.category() {
+            self.maybe_write_str_ref(*idx)?
+        }
+
+        for arg in event.arguments() {
+            let name_ref = arg.name();
+            if let StringRef::Ref(idx) = name_ref {
+                self.maybe_write_str_ref(*idx)?
+            }
+            if let Argument::Str(_, StringRef::Ref(idx)) = arg {
+                self.maybe_write_str_ref(*idx)?
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the thread and string records behind references decoded by [`wire`].
+    fn write_refs(&mut self, refs: &wire::Refs) -> Result<()> {
+        if self.options.prune_kernel_objects {
+            for &idx in &refs.threads {
+                let (process_koid, thread_koid) = self.thread_koids(&ThreadRef::Ref(idx))?;
+                self.reference_koid(process_koid)?;
+                self.reference_koid(thread_koid)?;
+            }
+            for &koid in &refs.koids {
+                self.reference_koid(koid)?;
+            }
+        }
+
+        for &idx in &refs.threads {
+            self.maybe_write_thread_ref(idx)?;
+        }
+        for &idx in &refs.strings {
+            self.maybe_write_str_ref(idx)?;
+        }
+        Ok(())
+    }
+}
+
+/// An event as a `--where` expression sees it, resolved against the cutter's current
+/// string and thread bindings.
+struct EventView<'a, R: Read + Seek, W: Write> {
+    cutter: &'a Cutter<R, W>,
+    event: &'a Event,
+}
+
+impl<R: Read + Seek, W: Write> EventFields for EventView<'_, R, W> {
+    fn timestamp(&self) -> u64 {
+        self.event.timestamp()
+    }
+
+    fn category(&self) -> Result<&str> {
+        self.cutter.str_value(self.event.category())
+    }
+
+    fn name(&self) -> Result<&str> {
+        self.cutter.str_value(self.event.name())
+    }
+
+    fn process_koid(&self) -> Result<u64> {
+        Ok(self.cutter.thread_koids(self.event.thread())?.0)
+    }
+
+    fn thread_koid(&self) -> Result<u64> {
+        Ok(self.cutter.thread_koids(self.event.thread())?.1)
+    }
+
+    fn any_arg(&self, f: &mut dyn FnMut(&str, ArgValue) -> bool) -> Result<bool> {
+        self.cutter.any_arg(self.event, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    // Helper to create test FTF data
     fn create_test_data() -> Vec<u8> {
         let mut buffer = Vec::new();
         
@@ -229,15 +1815,8 @@ mod tests {
         
         for record in &archive.records {
             if let Record::Event(event_record) = record {
-                let ts = match &event_record {
-                    EventRecord::DurationBegin(d) => d.event().timestamp(),
-                    EventRecord::DurationEnd(d) => d.event().timestamp(),
-                    EventRecord::DurationComplete(d) => d.event().timestamp(),
-                    EventRecord::Counter(c) => c.event().timestamp(),
-                    EventRecord::Instant(i) => i.event().timestamp(),
-                    _ => 0,
-                };
-                
+                let ts = event_of(event_record).timestamp();
+
                 if ts >= start_ts && ts <= end_ts {
                     count += 1;
                 }
@@ -264,6 +1843,26 @@ mod tests {
         (count, indices)
     }
 
+    // Helper to count how many times `needle` appears verbatim in `buffer`
+    fn count_occurrences(buffer: &[u8], needle: &[u8]) -> usize {
+        buffer.windows(needle.len()).filter(|window| *window == needle).count()
+    }
+
+    // Helper to collect the indices of thread records in a buffer
+    fn thread_record_indices(buffer: &[u8]) -> Vec<u8> {
+        let reader = Cursor::new(buffer);
+        let archive = ftfrs::Archive::read(reader).unwrap();
+
+        archive
+            .records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Thread(thread_rec) => Some(thread_rec.index()),
+                _ => None,
+            })
+            .collect()
+    }
+
     #[test]
     fn test_cutter_filters_by_timestamp() {
         // Create test data
@@ -358,6 +1957,1341 @@ mod tests {
         assert!(string_indices.contains(&4), "Output missing required string with index 4");
     }
 
+    #[test]
+    fn test_rebound_string_index_is_rewritten() {
+        let mut buffer = Vec::new();
+        Record::create_string(1, "first_name".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "category".to_string()).write(&mut buffer).unwrap();
+        Record::create_instant_event(
+            1000,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        // Rebind index 1 and reference it again from a kept event
+        Record::create_string(1, "second_name".to_string()).write(&mut buffer).unwrap();
+        Record::create_instant_event(
+            2000,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        cutter.cut().unwrap();
+
+        // Index 1 must be written once per binding, index 2 only once
+        let (_, string_indices) = count_string_records(&output_buffer);
+        assert_eq!(string_indices, vec![1, 2, 1], "Rebound string index should be re-emitted");
+    }
+
+    #[test]
+    fn test_only_referenced_threads_included() {
+        let mut buffer = create_test_data();
+
+        Record::create_thread(1, 10, 11).write(&mut buffer).unwrap();
+        Record::create_thread(2, 20, 21).write(&mut buffer).unwrap();
+
+        // In range, references thread 1
+        Record::create_instant_event(
+            2100,
+            ThreadRef::Ref(1),
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        // Out of range, references thread 2
+        Record::create_instant_event(
+            3100,
+            ThreadRef::Ref(2),
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        // Rebind thread 1 and reference it again from a kept event
+        Record::create_thread(1, 30, 31).write(&mut buffer).unwrap();
+        Record::create_instant_event(
+            2200,
+            ThreadRef::Ref(1),
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        cutter.cut().unwrap();
+
+        assert_eq!(
+            thread_record_indices(&output_buffer),
+            vec![1, 1],
+            "Only referenced threads should be written, once per binding"
+        );
+    }
+
+    #[test]
+    fn test_async_and_flow_events_filtered_by_timestamp() {
+        let mut buffer = create_test_data();
+        Record::create_string(5, "async_only".to_string()).write(&mut buffer).unwrap();
+
+        // In range
+        Record::create_async_begin_event(
+            1100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+            7, // async_correlation_id
+        ).write(&mut buffer).unwrap();
+        Record::create_flow_step_event(
+            1200,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+            9, // flow_correlation_id
+        ).write(&mut buffer).unwrap();
+
+        // Out of range, references a string nothing else uses
+        Record::create_async_end_event(
+            4000,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(5),
+            Vec::new(),
+            7, // async_correlation_id
+        ).write(&mut buffer).unwrap();
+        Record::create_flow_end_event(
+            4100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+            9, // flow_correlation_id
+        ).write(&mut buffer).unwrap();
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        cutter.cut().unwrap();
+
+        let event_count_output = count_events_in_buffer(&output_buffer, 0, u64::MAX);
+        assert_eq!(event_count_output, 5, "Only the async and flow events in range should be kept");
+
+        let (_, string_indices) = count_string_records(&output_buffer);
+        assert!(!string_indices.contains(&5), "Strings of dropped async events should not be written");
+    }
+
+    // Helper to list (is_begin, timestamp) for the duration begin/end events in a buffer
+    fn duration_edges(buffer: &[u8]) -> Vec<(bool, u64)> {
+        let reader = Cursor::new(buffer);
+        let archive = ftfrs::Archive::read(reader).unwrap();
+
+        archive
+            .records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Event(EventRecord::DurationBegin(d)) => Some((true, d.event().timestamp())),
+                Record::Event(EventRecord::DurationEnd(d)) => Some((false, d.event().timestamp())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_balance_slices_closes_window_boundaries() {
+        let mut buffer = create_test_data();
+
+        // Begins inside the window but never ends
+        Record::create_duration_begin_event(
+            2200,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let options = CutOptions {
+            balance_slices: true,
+            ..Default::default()
+        };
+        let mut cutter = Cutter::with_options(input_reader, output_writer, 500, 2500, options);
+        cutter.cut().unwrap();
+
+        // The begin at 100 is re-opened at 500 and the begin at 2200 is closed at 2500
+        assert_eq!(
+            duration_edges(&output_buffer),
+            vec![(true, 500), (false, 1000), (true, 2200), (false, 2500)]
+        );
+    }
+
+    #[test]
+    fn test_balanced_slices_written_in_thread_order() {
+        let mut buffer = Vec::new();
+        Record::create_string(1, "slice".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "category".to_string()).write(&mut buffer).unwrap();
+        for thread_koid in [7, 3, 9, 1, 5] {
+            Record::create_duration_begin_event(
+                200,
+                ThreadRef::Inline { process_koid: 0, thread_koid },
+                StringRef::Ref(2),
+                StringRef::Ref(1),
+                Vec::new(),
+            ).write(&mut buffer).unwrap();
+        }
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let options = CutOptions {
+            balance_slices: true,
+            ..Default::default()
+        };
+        let mut cutter = Cutter::with_options(input_reader, output_writer, 500, 600, options);
+        cutter.cut().unwrap();
+
+        let archive = ftfrs::Archive::read(Cursor::new(&output_buffer)).unwrap();
+        let thread_koids: Vec<(bool, u64)> = archive
+            .records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Event(EventRecord::DurationBegin(d)) => Some((true, d.event().thread())),
+                Record::Event(EventRecord::DurationEnd(d)) => Some((false, d.event().thread())),
+                _ => None,
+            })
+            .map(|(is_begin, thread)| match thread {
+                ThreadRef::Inline { thread_koid, .. } => (is_begin, *thread_koid),
+                ThreadRef::Ref(_) => panic!("Synthetic events should use inline threads"),
+            })
+            .collect();
+
+        let mut expected: Vec<_> = [1, 3, 5, 7, 9].map(|koid| (true, koid)).to_vec();
+        expected.extend([1, 3, 5, 7, 9].map(|koid| (false, koid)));
+        assert_eq!(thread_koids, expected);
+    }
+
+    // Helper to list the (start, end) timestamps of DurationComplete events in a buffer
+    fn complete_bounds(buffer: &[u8]) -> Vec<(u64, u64)> {
+        let reader = Cursor::new(buffer);
+        let archive = ftfrs::Archive::read(reader).unwrap();
+
+        archive
+            .records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Event(EventRecord::DurationComplete(d)) => {
+                    Some((d.event().timestamp(), d.end_ts()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_overlap_policies_for_straddling_slices() {
+        let mut buffer = create_test_data();
+
+        // Covers the whole window
+        Record::create_duration_complete_event(
+            100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+            3000, // end_ts
+        ).write(&mut buffer).unwrap();
+
+        // Starts just before the end of the window
+        Record::create_duration_complete_event(
+            2400,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+            2800, // end_ts
+        ).write(&mut buffer).unwrap();
+
+        let expectations = [
+            (OverlapPolicy::Start, vec![(1500, 1600), (2400, 2800)]),
+            (OverlapPolicy::Overlap, vec![(1500, 1600), (100, 3000), (2400, 2800)]),
+            (OverlapPolicy::Clip, vec![(1500, 1600), (500, 2500), (2400, 2500)]),
+        ];
+
+        for (overlap_policy, expected) in expectations {
+            let input_reader = Cursor::new(buffer.clone());
+            let mut output_buffer = Vec::new();
+            let output_writer = Cursor::new(&mut output_buffer);
+
+            let options = CutOptions {
+                overlap_policy,
+                ..Default::default()
+            };
+            let mut cutter = Cutter::with_options(input_reader, output_writer, 500, 2500, options);
+            cutter.cut().unwrap();
+
+            assert_eq!(complete_bounds(&output_buffer), expected);
+        }
+    }
+
+    #[test]
+    fn test_seed_counters_at_window_start() {
+        let mut buffer = Vec::new();
+        Record::create_string(1, "category".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "memory".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(3, "bytes".to_string()).write(&mut buffer).unwrap();
+
+        for (ts, value) in [(100, 10), (200, 20)] {
+            Record::create_counter_event(
+                ts,
+                ThreadRef::Inline { process_koid: 1, thread_koid: 2 },
+                StringRef::Ref(1),
+                StringRef::Ref(2),
+                vec![
+                    Argument::UInt64(StringRef::Ref(3), value),
+                    Argument::Str(StringRef::Ref(1), StringRef::Ref(2)),
+                ],
+                0, // counter_id
+            ).write(&mut buffer).unwrap();
+        }
+
+        // Rebinding the name index must not rename the remembered sample
+        Record::create_string(2, "unrelated".to_string()).write(&mut buffer).unwrap();
+        Record::create_instant_event(
+            1000,
+            ThreadRef::Inline { process_koid: 1, thread_koid: 2 },
+            StringRef::Ref(1),
+            StringRef::Ref(2),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let options = CutOptions {
+            seed_counters: true,
+            ..Default::default()
+        };
+        let mut cutter = Cutter::with_options(input_reader, output_writer, 500, 2500, options);
+        cutter.cut().unwrap();
+
+        let archive = ftfrs::Archive::read(Cursor::new(&output_buffer)).unwrap();
+        let counters: Vec<_> = archive
+            .records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Event(EventRecord::Counter(c)) => Some(c.event()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(counters.len(), 1, "Only the last pre-window sample should be seeded");
+        assert_eq!(counters[0].timestamp(), 500);
+        assert_eq!(counters[0].name(), &StringRef::Inline("memory".to_string()));
+        // The string argument is not a counter value, so the seeded sample leaves it out
+        assert_eq!(
+            counters[0].arguments(),
+            &[Argument::UInt64(StringRef::Inline("bytes".to_string()), 20)]
+        );
+    }
+
+    #[test]
+    fn test_truncated_record_reported() {
+        let mut buffer = create_test_data();
+        let last_record_offset = buffer.len() as u64;
+        Record::create_instant_event(
+            2100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+        buffer.truncate(buffer.len() - 8);
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.outcome, CutOutcome::Truncated { offset: last_record_offset });
+        assert_eq!(summary.records, 9, "All complete records should be counted");
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 3);
+    }
+
+    #[test]
+    fn test_truncated_header_reported() {
+        let mut buffer = create_test_data();
+        let complete_len = buffer.len() as u64;
+        buffer.extend_from_slice(&[0_u8; 3]);
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.outcome, CutOutcome::Truncated { offset: complete_len });
+    }
+
+    #[test]
+    fn test_lenient_skips_corrupt_record() {
+        let mut buffer = create_test_data();
+        let corrupt_offset = buffer.len() as u64;
+        // Reserved record type 10 with a size of 3 words
+        buffer.extend_from_slice(&(10_u64 | (3 << 4)).to_ne_bytes());
+        buffer.extend_from_slice(&[0xff_u8; 16]);
+        let resume_offset = buffer.len() as u64;
+        Record::create_instant_event(
+            2100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        // Strict mode aborts on the corrupt record
+        let mut output_buffer = Vec::new();
+        let mut cutter = Cutter::new(Cursor::new(buffer.clone()), Cursor::new(&mut output_buffer), 500, 2500);
+        assert!(cutter.cut().is_err(), "Corrupt record should abort a strict cut");
+
+        let mut output_buffer = Vec::new();
+        let options = CutOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let mut cutter =
+            Cutter::with_options(Cursor::new(buffer), Cursor::new(&mut output_buffer), 500, 2500, options);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.outcome, CutOutcome::Complete);
+        assert_eq!(summary.skipped, vec![(corrupt_offset, resume_offset)]);
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 4);
+    }
+
+    #[test]
+    fn test_lenient_skipped_record_leaves_no_binding() {
+        let mut buffer = create_test_data();
+        // Rebinds the event name, but its header declares a word more than the string needs
+        let corrupt_offset = buffer.len();
+        Record::create_string(1, "renamed".to_string()).write(&mut buffer).unwrap();
+        let header = u64::from_ne_bytes(buffer[corrupt_offset..corrupt_offset + 8].try_into().unwrap());
+        buffer[corrupt_offset..corrupt_offset + 8].copy_from_slice(&(header + (1 << 4)).to_ne_bytes());
+        buffer.extend_from_slice(&[0_u8; 8]);
+        Record::create_instant_event(
+            2100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let mut output_buffer = Vec::new();
+        let options = CutOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let mut cutter =
+            Cutter::with_options(Cursor::new(buffer), Cursor::new(&mut output_buffer), 500, 2500, options);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.skipped.len(), 1);
+        assert_eq!(summary.skipped[0].0, corrupt_offset as u64);
+        assert_eq!(count_occurrences(&output_buffer, b"renamed"), 0);
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 4);
+    }
+
+    #[test]
+    fn test_lenient_drops_record_with_missing_reference() {
+        let mut buffer = create_test_data();
+        // A well-formed event whose name index was never bound
+        let unresolved_offset = buffer.len() as u64;
+        Record::create_instant_event(
+            2100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(9),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+        Record::create_instant_event(
+            2200,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let mut output_buffer = Vec::new();
+        let mut cutter = Cutter::new(Cursor::new(buffer.clone()), Cursor::new(&mut output_buffer), 500, 2500);
+        assert!(cutter.cut().is_err(), "Missing reference should abort a strict cut");
+
+        let mut output_buffer = Vec::new();
+        let options = CutOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let mut cutter =
+            Cutter::with_options(Cursor::new(buffer), Cursor::new(&mut output_buffer), 500, 2500, options);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.unresolved, vec![unresolved_offset]);
+        assert!(summary.skipped.is_empty());
+        assert_eq!(count_events_in_buffer(&output_buffer, 2100, 2100), 0);
+        assert_eq!(count_events_in_buffer(&output_buffer, 2200, 2200), 1);
+    }
+
+    // Helper to encode an in-band large blob record with metadata and a one-word payload
+    fn large_blob_record(timestamp: u64, name_idx: u16) -> Vec<u8> {
+        let words = [
+            wire::LARGE_RECORD_TYPE | (7 << 4),
+            // Category index 2, name index `name_idx`, inline thread
+            2 | ((name_idx as u64) << 16),
+            timestamp,
+            0, // process koid
+            0, // thread koid
+            8, // blob size
+            0, // payload
+        ];
+        words.iter().flat_map(|word| word.to_ne_bytes()).collect()
+    }
+
+    #[test]
+    fn test_large_blobs_filtered_by_timestamp() {
+        let mut buffer = create_test_data();
+        Record::create_string(5, "blob_only".to_string()).write(&mut buffer).unwrap();
+        let kept_blob = large_blob_record(1200, 1);
+        buffer.extend_from_slice(&kept_blob);
+        let dropped_blob = large_blob_record(4000, 5);
+        buffer.extend_from_slice(&dropped_blob);
+        // Events after the blobs must still decode, so the blobs were skipped in sync
+        Record::create_instant_event(
+            2100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.outcome, CutOutcome::Complete);
+        assert_eq!(summary.records, 13);
+        assert_eq!(count_occurrences(&output_buffer, &kept_blob), 1, "The in-window blob should be copied verbatim");
+        assert_eq!(count_occurrences(&output_buffer, &dropped_blob), 0, "The blob after the window should be dropped");
+    }
+
+    // Helper to encode a legacy context switch record between two indexed threads
+    fn legacy_context_switch_record(timestamp: u64, outgoing: u8, incoming: u8) -> Vec<u8> {
+        let header = 8 | (2 << 4) | ((outgoing as u64) << 28) | ((incoming as u64) << 36);
+        [header, timestamp].iter().flat_map(|word| word.to_ne_bytes()).collect()
+    }
+
+    // Helper to encode a thread wakeup record with a single null argument
+    fn thread_wakeup_record(timestamp: u64, thread_koid: u64, arg_name_idx: u16) -> Vec<u8> {
+        let header = 8 | (4 << 4) | (1 << 16) | (2 << 60);
+        let argument = (1 << 4) | ((arg_name_idx as u64) << 16);
+        [header, timestamp, thread_koid, argument]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_scheduling_records_filtered_by_timestamp() {
+        let mut buffer = create_test_data();
+        let mut thread_one = Vec::new();
+        Record::create_thread(1, 10, 11).write(&mut thread_one).unwrap();
+        let mut thread_two = Vec::new();
+        Record::create_thread(2, 10, 12).write(&mut thread_two).unwrap();
+        let mut wakeup_string = Vec::new();
+        Record::create_string(5, "wakeup_reason".to_string()).write(&mut wakeup_string).unwrap();
+        buffer.extend_from_slice(&thread_one);
+        buffer.extend_from_slice(&thread_two);
+        buffer.extend_from_slice(&wakeup_string);
+
+        let kept_switch = legacy_context_switch_record(1200, 1, 1);
+        let kept_wakeup = thread_wakeup_record(1300, 11, 5);
+        let dropped_switch = legacy_context_switch_record(4000, 2, 2);
+        buffer.extend_from_slice(&kept_switch);
+        buffer.extend_from_slice(&kept_wakeup);
+        buffer.extend_from_slice(&dropped_switch);
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.outcome, CutOutcome::Complete);
+        assert_eq!(count_occurrences(&output_buffer, &kept_switch), 1);
+        assert_eq!(count_occurrences(&output_buffer, &kept_wakeup), 1);
+        assert_eq!(count_occurrences(&output_buffer, &dropped_switch), 0);
+        assert_eq!(count_occurrences(&output_buffer, &thread_one), 1, "Thread of a kept switch should be written");
+        assert_eq!(count_occurrences(&output_buffer, &thread_two), 0, "Thread of a dropped switch should not be written");
+        assert_eq!(count_occurrences(&output_buffer, &wakeup_string), 1, "Argument names should be written");
+    }
+
+    #[test]
+    fn test_lenient_skips_overrunning_scheduling_record() {
+        let mut buffer = create_test_data();
+        let corrupt_offset = buffer.len() as u64;
+        // Context switch whose one argument declares three words, running past the record
+        let header: u64 = 8 | (5 << 4) | (1 << 16) | (1 << 60);
+        let argument = (3 << 4) | (1 << 16);
+        for word in [header, 1530, 0, 0, argument] {
+            buffer.extend_from_slice(&word.to_ne_bytes());
+        }
+        let resume_offset = buffer.len() as u64;
+        Record::create_instant_event(
+            2100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let mut output_buffer = Vec::new();
+        let mut cutter = Cutter::new(Cursor::new(buffer.clone()), Cursor::new(&mut output_buffer), 500, 2500);
+        assert!(cutter.cut().is_err(), "Overrunning record should abort a strict cut");
+
+        let mut output_buffer = Vec::new();
+        let options = CutOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let mut cutter =
+            Cutter::with_options(Cursor::new(buffer), Cursor::new(&mut output_buffer), 500, 2500, options);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.skipped, vec![(corrupt_offset, resume_offset)]);
+        assert_eq!(count_occurrences(&output_buffer, &header.to_ne_bytes()), 0);
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 4);
+    }
+
+    // Helper to encode a provider section metadata record
+    fn provider_section_record(provider_id: u64) -> [u8; 8] {
+        (1 << 4 | 2 << 16 | provider_id << 20).to_ne_bytes()
+    }
+
+    #[test]
+    fn test_string_tables_scoped_per_provider() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&provider_section_record(1));
+        Record::create_string(1, "provider_one".to_string()).write(&mut buffer).unwrap();
+        buffer.extend_from_slice(&provider_section_record(2));
+        Record::create_string(1, "provider_two".to_string()).write(&mut buffer).unwrap();
+
+        // Back in provider 1, index 1 still refers to provider 1's string
+        buffer.extend_from_slice(&provider_section_record(1));
+        Record::create_instant_event(
+            1000,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Inline("category".to_string()),
+            StringRef::Ref(1),
+            Vec::new(),
+        ).write(&mut buffer).unwrap();
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        cutter.cut().unwrap();
+
+        let archive = ftfrs::Archive::read(Cursor::new(&output_buffer)).unwrap();
+        let strings: Vec<_> = archive
+            .records
+            .iter()
+            .filter_map(|record| match record {
+                Record::String(string_rec) => Some(string_rec.value().to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(strings, vec!["provider_one".to_string()]);
+    }
+
+    // Helper to encode a log record from an indexed thread
+    fn log_record(timestamp: u64, thread: u8, message: &[u8; 8]) -> Vec<u8> {
+        let header = 9 | (3 << 4) | (8 << 16) | ((thread as u64) << 32);
+        [header, timestamp, u64::from_ne_bytes(*message)]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_log_records_filtered_by_timestamp() {
+        let mut buffer = create_test_data();
+        let mut thread_one = Vec::new();
+        Record::create_thread(1, 10, 11).write(&mut thread_one).unwrap();
+        let mut thread_two = Vec::new();
+        Record::create_thread(2, 10, 12).write(&mut thread_two).unwrap();
+        buffer.extend_from_slice(&thread_one);
+        buffer.extend_from_slice(&thread_two);
+
+        let kept_log = log_record(1200, 1, b"in range");
+        let dropped_log = log_record(4000, 2, b"too late");
+        buffer.extend_from_slice(&kept_log);
+        buffer.extend_from_slice(&dropped_log);
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        let summary = cutter.cut().unwrap();
+
+        assert_eq!(summary.outcome, CutOutcome::Complete);
+        assert_eq!(count_occurrences(&output_buffer, &kept_log), 1);
+        assert_eq!(count_occurrences(&output_buffer, &dropped_log), 0);
+        assert_eq!(count_occurrences(&output_buffer, &thread_one), 1, "Thread of a kept log should be written");
+        assert_eq!(count_occurrences(&output_buffer, &thread_two), 0, "Thread of a dropped log should not be written");
+    }
+
+    // Helper to encode a kernel object record with a string-table name and no arguments
+    fn kernel_object_record(koid: u64, name_idx: u16) -> Vec<u8> {
+        let header = 7 | (2 << 4) | (1 << 16) | ((name_idx as u64) << 24);
+        [header, koid].iter().flat_map(|word| word.to_ne_bytes()).collect()
+    }
+
+    #[test]
+    fn test_strings_of_passthrough_records_written_first() {
+        let mut buffer = create_test_data();
+        let mut process_name = Vec::new();
+        Record::create_string(5, "compositor".to_string()).write(&mut process_name).unwrap();
+        let kernel_object = kernel_object_record(1000, 5);
+        buffer.extend_from_slice(&process_name);
+        buffer.extend_from_slice(&kernel_object);
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let mut cutter = Cutter::new(input_reader, output_writer, 500, 2500);
+        cutter.cut().unwrap();
+
+        let find = |needle: &[u8]| {
+            output_buffer
+                .windows(needle.len())
+                .position(|window| window == needle)
+        };
+        let string_pos = find(&process_name).expect("Kernel object name should be written");
+        let object_pos = find(&kernel_object).expect("Kernel object should be copied");
+        assert!(string_pos < object_pos, "Name must be defined before the kernel object");
+    }
+
+    // Helper to encode a thread kernel object record naming its process in a koid argument
+    fn thread_object_record(koid: u64, name_idx: u16, process_koid: u64) -> Vec<u8> {
+        let header = 7 | (4 << 4) | (2 << 16) | ((name_idx as u64) << 24) | (1 << 40);
+        let argument = 8 | (2 << 4) | ((name_idx as u64) << 16);
+        [header, koid, argument, process_koid]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect()
+    }
+
+    // Helper to encode a context switch record between two thread koids
+    fn context_switch_record(timestamp: u64, outgoing_koid: u64, incoming_koid: u64) -> Vec<u8> {
+        let header = 8 | (4 << 4) | (1 << 60);
+        [header, timestamp, outgoing_koid, incoming_koid]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_prune_kernel_objects() {
+        let mut buffer = create_test_data();
+        Record::create_string(5, "object_name".to_string()).write(&mut buffer).unwrap();
+        let process = kernel_object_record(10, 5);
+        let thread = thread_object_record(11, 5, 10);
+        let unrelated_thread = thread_object_record(12, 5, 10);
+        let late_thread = thread_object_record(13, 5, 10);
+        let context_switch = context_switch_record(1200, 11, 13);
+        buffer.extend_from_slice(&process);
+        buffer.extend_from_slice(&thread);
+        buffer.extend_from_slice(&unrelated_thread);
+        buffer.extend_from_slice(&context_switch);
+        // Described only after the context switch refers to it
+        buffer.extend_from_slice(&late_thread);
+
+        let input_reader = Cursor::new(buffer);
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let options = CutOptions {
+            prune_kernel_objects: true,
+            ..Default::default()
+        };
+        let mut cutter = Cutter::with_options(input_reader, output_writer, 500, 2500, options);
+        cutter.cut().unwrap();
+
+        let find = |needle: &[u8]| {
+            output_buffer
+                .windows(needle.len())
+                .position(|window| window == needle)
+        };
+        let switch_pos = find(&context_switch).expect("Context switch should be kept");
+        let thread_pos = find(&thread).expect("Switched-out thread should be kept");
+        let process_pos = find(&process).expect("Process of a kept thread should be kept");
+        assert!(process_pos < thread_pos && thread_pos < switch_pos);
+        assert!(find(&late_thread).is_some(), "Switched-in thread should be kept");
+        assert!(find(&unrelated_thread).is_none(), "Unreferenced thread should be dropped");
+    }
+
+    #[test]
+    fn test_window_in_time_units() {
+        let mut buffer = Vec::new();
+        // One tick per microsecond
+        Record::create_initialization(1_000_000).write(&mut buffer).unwrap();
+        buffer.extend_from_slice(&create_test_data());
+
+        let input_reader = Cursor::new(buffer.clone());
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+
+        let window: Window = "0.5ms..2500us".parse().unwrap();
+        let mut cutter = Cutter::with_window(input_reader, output_writer, window, CutOptions::default());
+        cutter.cut().unwrap();
+
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 3);
+
+        // Without the initialization record there is no way to convert the window
+        let input_reader = Cursor::new(create_test_data());
+        let mut output_buffer = Vec::new();
+        let output_writer = Cursor::new(&mut output_buffer);
+        let mut cutter = Cutter::with_window(input_reader, output_writer, window, CutOptions::default());
+        assert!(cutter.cut().is_err(), "Time units need an initialization record");
+    }
+
+    // Helper to cut `buffer` to `window` with `options`, returning the output
+    fn cut_with(buffer: &[u8], window: Window, options: CutOptions) -> Vec<u8> {
+        let mut output_buffer = Vec::new();
+        let mut cutter = Cutter::with_window(Cursor::new(buffer), Cursor::new(&mut output_buffer), window, options);
+        cutter.cut().unwrap();
+        output_buffer
+    }
+
+    #[test]
+    fn test_relative_windows() {
+        // Events run from 100 to 3000, one tick per microsecond
+        let mut buffer = Vec::new();
+        Record::create_initialization(1_000_000).write(&mut buffer).unwrap();
+        buffer.extend_from_slice(&create_test_data());
+
+        let cut = |range: &str| {
+            let output_buffer = cut_with(&buffer, range.parse().unwrap(), CutOptions::default());
+            count_events_in_buffer(&output_buffer, 0, u64::MAX)
+        };
+
+        // Instant at 2000 and counter at 3000
+        assert_eq!(cut("-1ms..end"), 2);
+        // Complete slice at 1500 and instant at 2000
+        assert_eq!(cut("+1400us..+1900us"), 2);
+        // Begin at 100 and end at 1000, with 33.4% of the way through at 1068
+        assert_eq!(cut("0%..33.4%"), 2);
+        assert_eq!(cut("start..end"), 5);
+    }
+
+    #[test]
+    fn test_rebase_timestamps() {
+        let mut buffer = create_test_data();
+        Record::create_thread(1, 10, 11).write(&mut buffer).unwrap();
+        buffer.extend_from_slice(&log_record(1200, 1, b"in range"));
+
+        let cut = |rebase| {
+            let options = CutOptions {
+                rebase,
+                ..CutOptions::default()
+            };
+            cut_with(&buffer, Window::from_ticks(1000, 2000), options)
+        };
+
+        let output_buffer = cut(Rebase::WindowStart);
+        // The end at 1000 moves to zero, the instant at 2000 to 1000
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, 0), 1);
+        assert_eq!(count_events_in_buffer(&output_buffer, 1000, 1000), 1);
+        assert_eq!(complete_bounds(&output_buffer), vec![(500, 600)]);
+        let rebased_log = log_record(200, 1, b"in range");
+        assert_eq!(count_occurrences(&output_buffer, &rebased_log), 1);
+
+        let output_buffer = cut(Rebase::By(Timestamp::Ticks(900)));
+        assert_eq!(count_events_in_buffer(&output_buffer, 100, 100), 1);
+        assert_eq!(complete_bounds(&output_buffer), vec![(600, 700)]);
+    }
+
+    #[test]
+    fn test_multiple_windows_in_one_pass() {
+        let input_reader = Cursor::new(create_test_data());
+        let mut early_buffer = Vec::new();
+        let mut late_buffer = Vec::new();
+        let windows = vec![
+            (Cursor::new(&mut early_buffer), Window::from_ticks(0, 1000)),
+            (Cursor::new(&mut late_buffer), Window::from_ticks(1100, 3000)),
+        ];
+        let mut cutter = Cutter::with_windows(input_reader, windows, CutOptions::default());
+        cutter.cut().unwrap();
+
+        // Begin and end in the first, complete, instant and counter in the second
+        assert_eq!(count_events_in_buffer(&early_buffer, 0, u64::MAX), 2);
+        assert_eq!(count_events_in_buffer(&late_buffer, 0, u64::MAX), 3);
+        // Each output carries every string its own events need
+        assert_eq!(count_string_records(&early_buffer).0, 4);
+        assert_eq!(count_string_records(&late_buffer).0, 4);
+    }
+
+    #[test]
+    fn test_numbered_output_path() {
+        let path = Path::new("out/cut.fxt");
+        assert_eq!(numbered_output_path(path, 2), PathBuf::from("out/cut-2.fxt"));
+        assert_eq!(numbered_output_path(Path::new("cut"), 1), PathBuf::from("cut-1"));
+    }
+
+    #[test]
+    fn test_cut_arguments_only_without_subcommand() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["ftf-cut", "-i", "in.fxt", "-o", "out.fxt", "-r", "0..100"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.cut.unwrap().input_path, PathBuf::from("in.fxt"));
+
+        let cli = Cli::try_parse_from(["ftf-cut", "split", "-i", "in.fxt", "-o", "out", "--every", "1s"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Split(_))));
+        assert!(cli.cut.is_none(), "Defaults alone should not make a cut");
+
+        assert!(Cli::try_parse_from(["ftf-cut", "-r", "0..100"]).is_err(), "A cut needs its paths");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("256MiB").unwrap(), 256 << 20);
+        assert_eq!(parse_size("2KB").unwrap(), 2000);
+        assert!(parse_size("1.5GiB").is_err(), "Sizes must be whole");
+        assert!(parse_size("1TiB").is_err(), "Unknown unit");
+    }
+
+    /// Output that writes into a buffer the test keeps a handle on.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.borrow_mut().flush()
+        }
+    }
+
+    fn split_chunks(input: Vec<u8>, limits: SplitLimits) -> Vec<Vec<u8>> {
+        let chunks: Rc<RefCell<Vec<SharedBuffer>>> = Rc::default();
+        let outputs = Rc::clone(&chunks);
+        let next_output = Box::new(move |_: usize| {
+            let buffer = SharedBuffer::default();
+            outputs.borrow_mut().push(buffer.clone());
+            Ok(buffer)
+        });
+        let mut cutter =
+            Cutter::splitting(Cursor::new(input), limits, next_output, CutOptions::default()).unwrap();
+        cutter.cut().unwrap();
+        drop(cutter);
+
+        let chunks = chunks.borrow();
+        chunks.iter().map(|chunk| chunk.0.borrow().clone()).collect()
+    }
+
+    #[test]
+    fn test_split_by_time() {
+        let mut init = Vec::new();
+        // One tick per microsecond
+        Record::create_initialization(1_000_000).write(&mut init).unwrap();
+        let mut buffer = init.clone();
+        buffer.extend_from_slice(&create_test_data());
+
+        let limits = SplitLimits {
+            every: Some("1ms".parse().unwrap()),
+            max_bytes: None,
+        };
+        let chunks = split_chunks(buffer, limits);
+
+        // Chunks start at 100, 2000 and 3000, with the complete slice at 1500 staying in
+        // the chunk it is read in
+        let events: Vec<_> = chunks
+            .iter()
+            .map(|chunk| count_events_in_buffer(chunk, 0, u64::MAX))
+            .collect();
+        assert_eq!(events, vec![2, 1, 2]);
+        for chunk in &chunks {
+            assert!(chunk.starts_with(&init), "Every chunk starts with the initialization record");
+            assert_eq!(count_string_records(chunk).0, 4, "Every chunk has its own strings");
+        }
+    }
+
+    #[test]
+    fn test_split_by_size() {
+        let buffer = create_test_data();
+        let limits = SplitLimits {
+            every: None,
+            max_bytes: Some(200),
+        };
+        let chunks = split_chunks(buffer, limits);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            // Each event brings its strings along, and only a chunk's first record may overflow it
+            if count_events_in_buffer(chunk, 0, u64::MAX) > 1 {
+                assert!(chunk.len() <= 200, "Chunk of {} bytes is over the limit", chunk.len());
+            }
+        }
+        let events: usize = chunks
+            .iter()
+            .map(|chunk| count_events_in_buffer(chunk, 0, u64::MAX))
+            .sum();
+        assert_eq!(events, 5, "Every event ends up in exactly one chunk");
+    }
+
+    #[test]
+    fn test_filter_by_process_and_thread() {
+        let mut buffer = create_test_data();
+        Record::create_string(5, "compositor_only".to_string()).write(&mut buffer).unwrap();
+        Record::create_thread(1, 10, 11).write(&mut buffer).unwrap();
+        // Indexed thread in process 10
+        Record::create_instant_event(
+            1200,
+            ThreadRef::Ref(1),
+            StringRef::Ref(2),
+            StringRef::Ref(5),
+            Vec::new(),
+        )
+        .write(&mut buffer)
+        .unwrap();
+        // Inline thread in process 10
+        Record::create_instant_event(
+            1300,
+            ThreadRef::Inline { process_koid: 10, thread_koid: 12 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+        )
+        .write(&mut buffer)
+        .unwrap();
+
+        let cut = |filter| {
+            let options = CutOptions {
+                filter,
+                ..CutOptions::default()
+            };
+            cut_with(&buffer, Window::from_ticks(500, 2500), options)
+        };
+
+        let output_buffer = cut(EventFilter {
+            pids: KoidFilter {
+                include: vec![10],
+                exclude: vec![],
+            },
+            ..EventFilter::default()
+        });
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 2);
+
+        let output_buffer = cut(EventFilter {
+            tids: KoidFilter {
+                include: vec![],
+                exclude: vec![11],
+            },
+            ..EventFilter::default()
+        });
+        // The end, instant and complete slice on thread 0, and the event on thread 12
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 4);
+        let (_, indices) = count_string_records(&output_buffer);
+        assert!(!indices.contains(&5), "Strings of dropped events stay out");
+        assert!(thread_record_indices(&output_buffer).is_empty());
+    }
+
+    #[test]
+    fn test_filter_by_category() {
+        let mut buffer = Vec::new();
+        Record::create_string(1, "event".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "gfx:present".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(3, "kernel:sched".to_string()).write(&mut buffer).unwrap();
+        let categories = [
+            StringRef::Ref(2),
+            StringRef::Ref(3),
+            StringRef::Inline("gfx".to_string()),
+            StringRef::Inline("kernel:irq".to_string()),
+            StringRef::Inline("input".to_string()),
+        ];
+        for (ts, category) in (1000..).zip(categories) {
+            Record::create_instant_event(
+                ts,
+                ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+                category,
+                StringRef::Ref(1),
+                Vec::new(),
+            )
+            .write(&mut buffer)
+            .unwrap();
+        }
+
+        let options = CutOptions {
+            filter: EventFilter {
+                categories: GlobFilter {
+                    include: vec!["gfx*".parse().unwrap(), "kernel:*".parse().unwrap()],
+                    exclude: vec!["kernel:sched".parse().unwrap()],
+                },
+                ..EventFilter::default()
+            },
+            ..CutOptions::default()
+        };
+        let output_buffer = cut_with(&buffer, Window::from_ticks(0, 2000), options);
+
+        // gfx:present, gfx and kernel:irq
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 3);
+        let (_, indices) = count_string_records(&output_buffer);
+        assert!(indices.contains(&2));
+        assert!(!indices.contains(&3), "Strings of dropped categories stay out");
+    }
+
+    #[test]
+    fn test_filter_by_name_regex() {
+        let mut buffer = Vec::new();
+        Record::create_string(1, "VsyncCallback".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "category".to_string()).write(&mut buffer).unwrap();
+        let add_instant = |buffer: &mut Vec<u8>, ts, name| {
+            Record::create_instant_event(
+                ts,
+                ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+                StringRef::Ref(2),
+                name,
+                Vec::new(),
+            )
+            .write(buffer)
+            .unwrap();
+        };
+        add_instant(&mut buffer, 1000, StringRef::Ref(1));
+        add_instant(&mut buffer, 1100, StringRef::Inline("VsyncDebug".to_string()));
+        add_instant(&mut buffer, 1200, StringRef::Inline("Layout".to_string()));
+        // Rebinding index 1 must not reuse the match result of its old string
+        Record::create_string(1, "Paint".to_string()).write(&mut buffer).unwrap();
+        add_instant(&mut buffer, 1300, StringRef::Ref(1));
+
+        let options = CutOptions {
+            filter: EventFilter {
+                names: RegexFilter {
+                    include: vec![Regex::new("^Vsync").unwrap()],
+                    exclude: vec![Regex::new("Debug$").unwrap()],
+                },
+                ..EventFilter::default()
+            },
+            ..CutOptions::default()
+        };
+        let output_buffer = cut_with(&buffer, Window::from_ticks(0, 2000), options);
+
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 1);
+        assert_eq!(count_events_in_buffer(&output_buffer, 1000, 1000), 1);
+    }
+
+    #[test]
+    fn test_filter_by_arg_predicates() {
+        let mut buffer = Vec::new();
+        Record::create_string(1, "event".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "category".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(3, "url".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(4, "/cart/checkout".to_string()).write(&mut buffer).unwrap();
+        let frame = |id| Argument::UInt64(StringRef::Inline("frame_id".to_string()), id);
+        let url = |value: &str| Argument::Str(StringRef::Ref(3), StringRef::Inline(value.to_string()));
+        let arguments = [
+            vec![frame(1234), Argument::Str(StringRef::Ref(3), StringRef::Ref(4))],
+            vec![frame(1234), url("/cart")],
+            vec![frame(1235), url("/checkout")],
+            vec![url("/checkout")],
+        ];
+        for (ts, arguments) in (1000..).zip(arguments) {
+            Record::create_instant_event(
+                ts,
+                ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+                StringRef::Ref(2),
+                StringRef::Ref(1),
+                arguments,
+            )
+            .write(&mut buffer)
+            .unwrap();
+        }
+
+        let cut = |args: &[&str]| {
+            let options = CutOptions {
+                filter: EventFilter {
+                    args: args.iter().map(|arg| arg.parse().unwrap()).collect(),
+                    ..EventFilter::default()
+                },
+                ..CutOptions::default()
+            };
+            cut_with(&buffer, Window::from_ticks(0, 2000), options)
+        };
+
+        assert_eq!(count_events_in_buffer(&cut(&["frame_id==1234"]), 0, u64::MAX), 2);
+        assert_eq!(count_events_in_buffer(&cut(&["frame_id>1234"]), 0, u64::MAX), 1);
+        assert_eq!(count_events_in_buffer(&cut(&["url*=checkout"]), 0, u64::MAX), 3);
+        let output_buffer = cut(&["frame_id<=1234", "url*=checkout"]);
+        assert_eq!(count_events_in_buffer(&output_buffer, 1000, 1000), 1);
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 1);
+    }
+
+    #[test]
+    fn test_balanced_slices_follow_filtered_begins() {
+        let mut buffer = Vec::new();
+        Record::create_string(1, "slice".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "category".to_string()).write(&mut buffer).unwrap();
+        let frame = |id| vec![Argument::UInt64(StringRef::Inline("frame_id".to_string()), id)];
+        // Only the begins carry the argument the filter looks for
+        let edges = [
+            (100, true, frame(1)),
+            (1000, false, Vec::new()),
+            (1200, true, frame(2)),
+            (1300, false, Vec::new()),
+            (2200, true, frame(1)),
+        ];
+        for (ts, is_begin, arguments) in edges {
+            let thread = ThreadRef::Inline { process_koid: 0, thread_koid: 0 };
+            let (category, name) = (StringRef::Ref(2), StringRef::Ref(1));
+            let record = if is_begin {
+                Record::create_duration_begin_event(ts, thread, category, name, arguments)
+            } else {
+                Record::create_duration_end_event(ts, thread, category, name, arguments)
+            };
+            record.write(&mut buffer).unwrap();
+        }
+
+        let options = CutOptions {
+            balance_slices: true,
+            filter: EventFilter {
+                args: vec!["frame_id==1".parse().unwrap()],
+                ..EventFilter::default()
+            },
+            ..CutOptions::default()
+        };
+        let output_buffer = cut_with(&buffer, Window::from_ticks(500, 2500), options);
+
+        // The first slice keeps its real end, and the one for frame 2 is dropped whole
+        assert_eq!(
+            duration_edges(&output_buffer),
+            vec![(true, 500), (false, 1000), (true, 2200), (false, 2500)]
+        );
+    }
+
+    #[test]
+    fn test_filter_by_where_expression() {
+        let mut buffer = Vec::new();
+        Record::create_initialization(1_000).write(&mut buffer).unwrap();
+        Record::create_string(1, "gfx".to_string()).write(&mut buffer).unwrap();
+        Record::create_string(2, "VsyncCallback".to_string()).write(&mut buffer).unwrap();
+        let events = [
+            (1000, 5, StringRef::Ref(1), StringRef::Ref(2)),
+            (1500, 4, StringRef::Ref(1), StringRef::Inline("Layout".to_string())),
+            (1600, 4, StringRef::Inline("input".to_string()), StringRef::Ref(2)),
+            (1700, 4, StringRef::Inline("input".to_string()), StringRef::Inline("Key".to_string())),
+            (2500, 4, StringRef::Ref(1), StringRef::Ref(2)),
+        ];
+        for (ts, process_koid, category, name) in events {
+            Record::create_instant_event(
+                ts,
+                ThreadRef::Inline { process_koid, thread_koid: 0 },
+                category,
+                name,
+                vec![Argument::UInt64(StringRef::Inline("frame_id".to_string()), ts)],
+            )
+            .write(&mut buffer)
+            .unwrap();
+        }
+
+        let cut = |condition: &str| {
+            let options = CutOptions {
+                filter: EventFilter {
+                    condition: Some(condition.parse().unwrap()),
+                    ..EventFilter::default()
+                },
+                ..CutOptions::default()
+            };
+            cut_with(&buffer, Window::from_ticks(0, 3000), options)
+        };
+
+        let output_buffer = cut(r#"ts in 1s..2s and (cat == "gfx" or name ~ "Vsync.*") and pid != 5"#);
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 2);
+        assert_eq!(count_events_in_buffer(&output_buffer, 1500, 1600), 2);
+        // Relative times are resolved by scanning the trace first
+        assert_eq!(count_events_in_buffer(&cut("ts >= -1s"), 0, u64::MAX), 4);
+        assert_eq!(count_events_in_buffer(&cut("not arg.frame_id < 1700"), 0, u64::MAX), 2);
+    }
+
+    #[test]
+    fn test_filter_by_kind() {
+        let mut buffer = create_test_data();
+        Record::create_async_begin_event(
+            1100,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+            7, // async_correlation_id
+        ).write(&mut buffer).unwrap();
+        Record::create_flow_step_event(
+            1200,
+            ThreadRef::Inline { process_koid: 0, thread_koid: 0 },
+            StringRef::Ref(2),
+            StringRef::Ref(1),
+            Vec::new(),
+            9, // flow_correlation_id
+        ).write(&mut buffer).unwrap();
+
+        let cut = |kinds| {
+            let options = CutOptions {
+                filter: EventFilter {
+                    kinds,
+                    ..EventFilter::default()
+                },
+                ..CutOptions::default()
+            };
+            cut_with(&buffer, Window::from_ticks(0, u64::MAX), options)
+        };
+
+        // Begin, end and complete durations, and the flow step
+        let output_buffer = cut(vec![EventKind::Duration, EventKind::Flow]);
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 4);
+        assert_eq!(count_events_in_buffer(&output_buffer, 1200, 1200), 1);
+        let output_buffer = cut(vec![EventKind::Counter]);
+        assert_eq!(count_events_in_buffer(&output_buffer, 3000, 3000), 1);
+        assert_eq!(count_events_in_buffer(&output_buffer, 0, u64::MAX), 1);
+        assert_eq!(count_events_in_buffer(&cut(Vec::new()), 0, u64::MAX), 7);
+    }
+
     #[test]
     fn test_process_event_within_range() {
         // Create a test event within range
@@ -414,6 +3348,7 @@ mod tests {
         let result = cutter.cut();
         
         assert!(result.is_ok(), "Cutting empty input should not error");
+        assert_eq!(result.unwrap().outcome, CutOutcome::Complete);
         assert_eq!(output_buffer.len(), 0, "Output should be empty for empty input");
     }
 }
@@ -0,0 +1,308 @@
+//! Window bounds as given on the command line, in raw ticks or real time units, and
+//! either absolute or relative to the span of the trace.
+
+use anyhow::{Context, Result, anyhow};
+use std::str::FromStr;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+/// Percentages are kept in millionths of a percent.
+const PERCENT_SCALE: u128 = 1_000_000;
+
+const UNITS: [(&str, u128); 4] = [
+    ("ns", 1),
+    ("us", 1_000),
+    ("ms", 1_000_000),
+    ("s", NANOS_PER_SECOND),
+];
+
+/// A time or offset on the trace clock. Values in time units can only be turned into
+/// ticks once the trace's initialization record has given its tick rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    Ticks(u64),
+    Nanos(u128),
+}
+
+impl Timestamp {
+    /// The tick value, if it does not depend on the tick rate.
+    pub fn ticks(self) -> Option<u64> {
+        match self {
+            Timestamp::Ticks(ticks) => Some(ticks),
+            Timestamp::Nanos(_) => None,
+        }
+    }
+
+    pub fn to_ticks(self, ticks_per_second: u64) -> Result<u64> {
+        match self {
+            Timestamp::Ticks(ticks) => Ok(ticks),
+            Timestamp::Nanos(nanos) => nanos
+                .checked_mul(ticks_per_second as u128)
+                .and_then(|scaled| u64::try_from(scaled / NANOS_PER_SECOND).ok())
+                .ok_or_else(|| anyhow!("{nanos}ns is out of range of the trace clock")),
+        }
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = anyhow::Error;
+
+    /// Parses plain tick counts such as `1000`, or times such as `1.25s`, `300ms`,
+    /// `42us` and `17ns`.
+    fn from_str(value: &str) -> Result<Self> {
+        let unit_start = value
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(unit_start);
+        if unit.is_empty() {
+            let ticks = number
+                .parse()
+                .with_context(|| format!("Invalid tick count `{value}`"))?;
+            return Ok(Timestamp::Ticks(ticks));
+        }
+
+        let Some(&(_, scale)) = UNITS.iter().find(|(name, _)| *name == unit) else {
+            return Err(anyhow!("Unknown time unit `{unit}` in `{value}`, expected s, ms, us or ns"));
+        };
+        let nanos = parse_scaled(number, scale).with_context(|| format!("Invalid time `{value}`"))?;
+        Ok(Timestamp::Nanos(nanos))
+    }
+}
+
+/// Parses a non-negative decimal number and multiplies it by `scale`, without going
+/// through floating point. Digits finer than one unit of the result are dropped.
+fn parse_scaled(number: &str, scale: u128) -> Result<u128> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if whole.len() + fraction.len() == 0 || !is_digits(whole) || !is_digits(fraction) {
+        return Err(anyhow!("Expected a decimal number, got `{number}`"));
+    }
+
+    let whole = if whole.is_empty() { 0 } else { whole.parse::<u128>()? };
+    let too_large = || anyhow!("`{number}` is too large");
+    let mut value = whole.checked_mul(scale).ok_or_else(too_large)?;
+    let mut place = scale;
+    for digit in fraction.chars() {
+        place /= 10;
+        value = value
+            .checked_add(digit.to_digit(10).unwrap() as u128 * place)
+            .ok_or_else(too_large)?;
+    }
+    Ok(value)
+}
+
+/// What window bounds are resolved against.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceClock {
+    pub ticks_per_second: Option<u64>,
+    /// Earliest and latest event timestamps, when the trace has been scanned for them.
+    pub span: Option<(u64, u64)>,
+}
+
+impl TraceClock {
+    /// Converts a time or an offset to ticks.
+    pub fn ticks(&self, time: Timestamp) -> Result<u64> {
+        match (time.ticks(), self.ticks_per_second) {
+            (Some(ticks), _) => Ok(ticks),
+            (None, Some(ticks_per_second)) => time.to_ticks(ticks_per_second),
+            (None, None) => Err(anyhow!(
+                "Times in units need the tick rate, but the trace has no initialization record"
+            )),
+        }
+    }
+
+    fn span(&self) -> Result<(u64, u64)> {
+        self.span
+            .ok_or_else(|| anyhow!("Relative window bounds need a trace with at least one event"))
+    }
+}
+
+/// One end of the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// An absolute time, like `1000` or `1.25s`.
+    At(Timestamp),
+    /// An offset from the first event, like `+500ms`.
+    AfterStart(Timestamp),
+    /// An offset back from the last event, like `-2s`.
+    BeforeEnd(Timestamp),
+    /// A point part-way through the trace, like `40%`, in millionths of a percent.
+    Percent(u128),
+    /// The first event, `start`.
+    Start,
+    /// The last event, `end`.
+    End,
+}
+
+impl Bound {
+    /// Whether resolving this bound needs the first and last event timestamps.
+    pub fn is_relative(self) -> bool {
+        !matches!(self, Bound::At(_))
+    }
+
+    /// The bound in ticks, if it does not depend on anything read from the trace.
+    pub fn ticks(self) -> Option<u64> {
+        match self {
+            Bound::At(time) => time.ticks(),
+            _ => None,
+        }
+    }
+
+    pub fn to_ticks(self, clock: &TraceClock) -> Result<u64> {
+        match self {
+            Bound::At(time) => clock.ticks(time),
+            Bound::AfterStart(offset) => Ok(clock.span()?.0.saturating_add(clock.ticks(offset)?)),
+            Bound::BeforeEnd(offset) => Ok(clock.span()?.1.saturating_sub(clock.ticks(offset)?)),
+            Bound::Percent(fraction) => {
+                let (first, last) = clock.span()?;
+                let into = (last - first) as u128 * fraction / (100 * PERCENT_SCALE);
+                Ok(first + into as u64)
+            }
+            Bound::Start => Ok(clock.span()?.0),
+            Bound::End => Ok(clock.span()?.1),
+        }
+    }
+}
+
+impl FromStr for Bound {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        if value == "start" {
+            Ok(Bound::Start)
+        } else if value == "end" {
+            Ok(Bound::End)
+        } else if let Some(percent) = value.strip_suffix('%') {
+            let fraction = parse_scaled(percent, PERCENT_SCALE)
+                .with_context(|| format!("Invalid percentage `{value}`"))?;
+            if fraction > 100 * PERCENT_SCALE {
+                return Err(anyhow!("Percentage `{value}` is over 100%"));
+            }
+            Ok(Bound::Percent(fraction))
+        } else if let Some(offset) = value.strip_prefix('+') {
+            Ok(Bound::AfterStart(offset.parse()?))
+        } else if let Some(offset) = value.strip_prefix('-') {
+            Ok(Bound::BeforeEnd(offset.parse()?))
+        } else {
+            Ok(Bound::At(value.parse()?))
+        }
+    }
+}
+
+/// The inclusive window to cut, before it has been converted to ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Window {
+    pub start: Bound,
+    pub end: Bound,
+}
+
+impl Window {
+    pub fn from_ticks(start_ts: u64, end_ts: u64) -> Self {
+        Self {
+            start: Bound::At(Timestamp::Ticks(start_ts)),
+            end: Bound::At(Timestamp::Ticks(end_ts)),
+        }
+    }
+
+    /// The window in ticks, if it does not depend on anything read from the trace.
+    pub fn ticks(&self) -> Option<(u64, u64)> {
+        Some((self.start.ticks()?, self.end.ticks()?))
+    }
+
+    pub fn is_relative(&self) -> bool {
+        self.start.is_relative() || self.end.is_relative()
+    }
+
+    pub fn to_ticks(&self, clock: &TraceClock) -> Result<(u64, u64)> {
+        Ok((self.start.to_ticks(clock)?, self.end.to_ticks(clock)?))
+    }
+}
+
+impl FromStr for Window {
+    type Err = anyhow::Error;
+
+    /// Parses `<start>..<end>`, such as `+500ms..+700ms`, `-2s..end` or `40%..45%`.
+    fn from_str(value: &str) -> Result<Self> {
+        let Some((start, end)) = value.split_once("..") else {
+            return Err(anyhow!("Expected a range like `<start>..<end>`, got `{value}`"));
+        };
+        Ok(Self {
+            start: start.parse()?,
+            end: end.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamps() {
+        assert_eq!("1000".parse::<Timestamp>().unwrap(), Timestamp::Ticks(1000));
+        assert_eq!("1.25s".parse::<Timestamp>().unwrap(), Timestamp::Nanos(1_250_000_000));
+        assert_eq!("300ms".parse::<Timestamp>().unwrap(), Timestamp::Nanos(300_000_000));
+        assert_eq!("42us".parse::<Timestamp>().unwrap(), Timestamp::Nanos(42_000));
+        assert_eq!("17ns".parse::<Timestamp>().unwrap(), Timestamp::Nanos(17));
+        assert_eq!(".5ms".parse::<Timestamp>().unwrap(), Timestamp::Nanos(500_000));
+
+        assert!("1.5".parse::<Timestamp>().is_err(), "Ticks must be whole");
+        assert!("10m".parse::<Timestamp>().is_err(), "Unknown unit");
+        assert!("s".parse::<Timestamp>().is_err(), "Missing number");
+        assert!("-1s".parse::<Timestamp>().is_err(), "Negative time");
+        assert!(
+            "340282366920938463463374607431.9s".parse::<Timestamp>().is_err(),
+            "Fraction overflows"
+        );
+    }
+
+    #[test]
+    fn test_to_ticks() {
+        let tick_rate = 24_000_000;
+        assert_eq!(Timestamp::Nanos(1_250_000_000).to_ticks(tick_rate).unwrap(), 30_000_000);
+        assert_eq!(Timestamp::Ticks(5).to_ticks(tick_rate).unwrap(), 5);
+        assert!(Timestamp::Nanos(u128::MAX / tick_rate as u128).to_ticks(tick_rate).is_err());
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let window: Window = "+500ms..+700ms".parse().unwrap();
+        assert_eq!(window.start, Bound::AfterStart(Timestamp::Nanos(500_000_000)));
+        assert_eq!(window.end, Bound::AfterStart(Timestamp::Nanos(700_000_000)));
+
+        let window: Window = "-2s..end".parse().unwrap();
+        assert_eq!(window.start, Bound::BeforeEnd(Timestamp::Nanos(2_000_000_000)));
+        assert_eq!(window.end, Bound::End);
+
+        let window: Window = "40%..45.5%".parse().unwrap();
+        assert_eq!(window.start, Bound::Percent(40_000_000));
+        assert_eq!(window.end, Bound::Percent(45_500_000));
+
+        // Only plain tick counts are known without the trace
+        assert_eq!("100..200".parse::<Window>().unwrap().ticks(), Some((100, 200)));
+        assert_eq!("100..1s".parse::<Window>().unwrap().ticks(), None);
+        assert_eq!("start..200".parse::<Window>().unwrap().ticks(), None);
+
+        assert!("1s".parse::<Window>().is_err(), "Missing range separator");
+        assert!("0%..101%".parse::<Window>().is_err(), "Percentage over 100");
+    }
+
+    #[test]
+    fn test_resolve_relative_windows() {
+        let clock = TraceClock {
+            ticks_per_second: Some(1_000),
+            span: Some((10_000, 20_000)),
+        };
+        let resolve = |range: &str| range.parse::<Window>().unwrap().to_ticks(&clock).unwrap();
+
+        assert_eq!(resolve("+500ms..+700ms"), (10_500, 10_700));
+        assert_eq!(resolve("-2s..end"), (18_000, 20_000));
+        assert_eq!(resolve("40%..45%"), (14_000, 14_500));
+        assert_eq!(resolve("start..15000"), (10_000, 15_000));
+
+        let no_events = TraceClock {
+            ticks_per_second: Some(1_000),
+            span: None,
+        };
+        assert!("-2s..end".parse::<Window>().unwrap().to_ticks(&no_events).is_err());
+    }
+}
@@ -0,0 +1,469 @@
+//! Word-level decoding of the records that `ftfrs` passes through as opaque bytes.
+
+/// Record type of large records, whose size does not fit the standard header field.
+pub const LARGE_RECORD_TYPE: u64 = 15;
+
+const METADATA_RECORD_TYPE: u64 = 0;
+const INITIALIZATION_RECORD_TYPE: u64 = 1;
+const EVENT_RECORD_TYPE: u64 = 4;
+const BLOB_RECORD_TYPE: u64 = 5;
+const USERSPACE_OBJECT_RECORD_TYPE: u64 = 6;
+const KERNEL_OBJECT_RECORD_TYPE: u64 = 7;
+const SCHEDULING_RECORD_TYPE: u64 = 8;
+const LOG_RECORD_TYPE: u64 = 9;
+
+const PROVIDER_INFO_METADATA: u64 = 1;
+const PROVIDER_SECTION_METADATA: u64 = 2;
+const TRACE_INFO_METADATA: u64 = 4;
+
+const STR_ARGUMENT_TYPE: u64 = 6;
+const KOID_ARGUMENT_TYPE: u64 = 8;
+
+const LEGACY_CONTEXT_SWITCH: u64 = 0;
+const CONTEXT_SWITCH: u64 = 1;
+const THREAD_WAKEUP: u64 = 2;
+
+const DURATION_COMPLETE_EVENT: u64 = 4;
+
+const LARGE_BLOB_TYPE: u64 = 0;
+const BLOB_FORMAT_METADATA: u64 = 0;
+const BLOB_FORMAT_NO_METADATA: u64 = 1;
+
+/// Extracts the `len`-bit field starting at bit `start` of `word`.
+fn bits(word: u64, start: u32, len: u32) -> u64 {
+    (word >> start) & ((1 << len) - 1)
+}
+
+/// Length in bytes of the record whose header word is `header`. Large records keep a
+/// 32-bit word count in bits 4..36 in place of the standard 12-bit size field.
+pub fn record_len(header: u64) -> u64 {
+    let words = if bits(header, 0, 4) == LARGE_RECORD_TYPE {
+        bits(header, 4, 32)
+    } else {
+        bits(header, 4, 12)
+    };
+    words * 8
+}
+
+/// Returns the provider that becomes current after the metadata record with header
+/// `header`, if it is a provider info or provider section record.
+pub fn provider_switch(header: u64) -> Option<u32> {
+    if bits(header, 0, 4) != METADATA_RECORD_TYPE {
+        return None;
+    }
+    match bits(header, 16, 4) {
+        PROVIDER_INFO_METADATA | PROVIDER_SECTION_METADATA => Some(bits(header, 20, 32) as u32),
+        _ => None,
+    }
+}
+
+/// Whether the metadata record with header `header` describes the whole trace rather
+/// than the point it appears at: provider info records and trace info records such as
+/// the magic number record.
+pub fn is_trace_wide_metadata(header: u64) -> bool {
+    bits(header, 0, 4) == METADATA_RECORD_TYPE
+        && matches!(bits(header, 16, 4), PROVIDER_INFO_METADATA | TRACE_INFO_METADATA)
+}
+
+/// Returns the provider a provider info record declares.
+pub fn provider_info(header: u64) -> Option<u32> {
+    if bits(header, 0, 4) != METADATA_RECORD_TYPE || bits(header, 16, 4) != PROVIDER_INFO_METADATA {
+        return None;
+    }
+    Some(bits(header, 20, 32) as u32)
+}
+
+/// Returns the tick rate stored in an initialization record.
+pub fn ticks_per_second(words: &[u64]) -> Option<u64> {
+    if bits(*words.first()?, 0, 4) != INITIALIZATION_RECORD_TYPE {
+        return None;
+    }
+    words.get(1).copied()
+}
+
+/// Returns the timestamp of an event record from its first two words.
+pub fn event_timestamp(words: &[u64]) -> Option<u64> {
+    if bits(*words.first()?, 0, 4) != EVENT_RECORD_TYPE {
+        return None;
+    }
+    words.get(1).copied()
+}
+
+/// Moves the timestamp in word `index` of a record back by `by` ticks, stopping at zero.
+pub fn rebase_word(record: &mut [u8], index: usize, by: u64) {
+    let bytes: &mut [u8; 8] = (&mut record[index * 8..index * 8 + 8]).try_into().unwrap();
+    *bytes = u64::from_ne_bytes(*bytes).saturating_sub(by).to_ne_bytes();
+}
+
+/// Moves the timestamps of an event record back by `by` ticks: its own timestamp, and
+/// the end time that `DurationComplete` events keep in their last word.
+pub fn rebase_event(record: &mut [u8], by: u64) {
+    let header = u64::from_ne_bytes(record[..8].try_into().unwrap());
+    rebase_word(record, 1, by);
+    if bits(header, 16, 4) == DURATION_COMPLETE_EVENT {
+        rebase_word(record, record.len() / 8 - 1, by);
+    }
+}
+
+/// Header of a one-word provider section record that switches to `provider`.
+pub fn provider_section_header(provider: u32) -> u64 {
+    METADATA_RECORD_TYPE | (1 << 4) | (PROVIDER_SECTION_METADATA << 16) | ((provider as u64) << 20)
+}
+
+/// Reinterprets a record's bytes as its sequence of words.
+pub fn to_words(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+/// String and thread table indexes referenced by a record, along with the koids it
+/// refers to directly.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Refs {
+    pub strings: Vec<u16>,
+    pub threads: Vec<u8>,
+    pub koids: Vec<u64>,
+}
+
+/// Walks the words of a record, collecting the table references it passes over.
+struct Reader<'a> {
+    words: &'a [u64],
+    pos: usize,
+    refs: Refs,
+}
+
+impl<'a> Reader<'a> {
+    fn new(words: &'a [u64]) -> Self {
+        Self {
+            words,
+            pos: 0,
+            refs: Refs::default(),
+        }
+    }
+
+    fn word(&mut self) -> Option<u64> {
+        let word = *self.words.get(self.pos)?;
+        self.pos += 1;
+        Some(word)
+    }
+
+    fn skip(&mut self, words: usize) -> Option<()> {
+        self.pos += words;
+        (self.pos <= self.words.len()).then_some(())
+    }
+
+    /// Consumes a string reference field: an index into the string table, or the
+    /// length of an inline string padded to whole words.
+    fn string(&mut self, field: u64) -> Option<()> {
+        if field & 0x8000 != 0 {
+            self.skip((field & 0x7fff).div_ceil(8) as usize)
+        } else {
+            self.string_index(field);
+            Some(())
+        }
+    }
+
+    /// Records a string reference field if it is an index, without consuming any words.
+    fn string_index(&mut self, field: u64) {
+        if field != 0 && field & 0x8000 == 0 {
+            self.refs.strings.push(field as u16);
+        }
+    }
+
+    /// Consumes a thread reference field: an index into the thread table, or zero for
+    /// inline process and thread koids.
+    fn thread(&mut self, field: u64) -> Option<()> {
+        if field == 0 {
+            let process_koid = self.word()?;
+            let thread_koid = self.word()?;
+            self.refs.koids.extend([process_koid, thread_koid]);
+            Some(())
+        } else {
+            self.refs.threads.push(field as u8);
+            Some(())
+        }
+    }
+
+    /// Consumes `count` arguments, recording their names, string values and koid values.
+    fn arguments(&mut self, count: u64) -> Option<()> {
+        for _ in 0..count {
+            let start = self.pos;
+            let header = self.word()?;
+            let size = bits(header, 4, 12) as usize;
+            if size == 0 {
+                return None;
+            }
+            self.string(bits(header, 16, 16))?;
+            match bits(header, 0, 4) {
+                STR_ARGUMENT_TYPE => self.string_index(bits(header, 32, 16)),
+                KOID_ARGUMENT_TYPE => {
+                    let koid = self.word()?;
+                    self.refs.koids.push(koid);
+                }
+                _ => {}
+            }
+            // Inline string values are covered by the argument size
+            self.pos = start;
+            self.skip(size)?;
+        }
+        Some(())
+    }
+
+    /// Completes the decoding, taking the timestamp from word `timestamp_word` if the
+    /// record has one.
+    fn finish(self, timestamp_word: Option<usize>) -> Option<Decoded> {
+        Some(Decoded {
+            timestamp: timestamp_word.map(|index| self.words[index]),
+            timestamp_word: timestamp_word.unwrap_or(0),
+            refs: self.refs,
+            object_koid: None,
+        })
+    }
+}
+
+/// The parts of an opaque record that decide whether it is kept.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Decoded {
+    /// `None` for records that are not tied to a point in time.
+    pub timestamp: Option<u64>,
+    /// Index of the word holding `timestamp`, or zero if there is none.
+    pub timestamp_word: usize,
+    pub refs: Refs,
+    /// The koid a kernel object record describes.
+    pub object_koid: Option<u64>,
+}
+
+/// A record that [`decode`] cannot walk: one whose fields run past its end, or of a
+/// scheduling type or blob format this tool does not know.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Malformed;
+
+impl std::fmt::Display for Malformed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Record fields run past its end or have an unknown type")
+    }
+}
+
+impl std::error::Error for Malformed {}
+
+/// Decodes a record that ftfrs leaves opaque from its words, header included. Returns
+/// `None` for records that reference nothing, such as metadata and initialization
+/// records, and [`Malformed`] for records that cannot be walked.
+pub fn decode(words: &[u64]) -> Result<Option<Decoded>, Malformed> {
+    let &header = words.first().ok_or(Malformed)?;
+    let decoded = match bits(header, 0, 4) {
+        BLOB_RECORD_TYPE => decode_blob(words),
+        USERSPACE_OBJECT_RECORD_TYPE => decode_userspace_object(words),
+        KERNEL_OBJECT_RECORD_TYPE => decode_kernel_object(words),
+        SCHEDULING_RECORD_TYPE => decode_scheduling(words),
+        LOG_RECORD_TYPE => decode_log(words),
+        // Only large blobs carry references; other large records pass through
+        LARGE_RECORD_TYPE if bits(header, 36, 4) == LARGE_BLOB_TYPE => decode_large_blob(words),
+        _ => return Ok(None),
+    };
+    decoded.map(Some).ok_or(Malformed)
+}
+
+fn decode_blob(words: &[u64]) -> Option<Decoded> {
+    let mut reader = Reader::new(words);
+    let header = reader.word()?;
+    reader.string(bits(header, 16, 16))?;
+    reader.finish(None)
+}
+
+fn decode_userspace_object(words: &[u64]) -> Option<Decoded> {
+    let mut reader = Reader::new(words);
+    let header = reader.word()?;
+    reader.skip(1)?; // pointer
+    reader.thread(bits(header, 16, 8))?;
+    reader.string(bits(header, 24, 16))?;
+    reader.arguments(bits(header, 40, 4))?;
+    reader.finish(None)
+}
+
+fn decode_kernel_object(words: &[u64]) -> Option<Decoded> {
+    let mut reader = Reader::new(words);
+    let header = reader.word()?;
+    let koid = reader.word()?;
+    reader.string(bits(header, 24, 16))?;
+    reader.arguments(bits(header, 40, 4))?;
+    let mut decoded = reader.finish(None)?;
+    decoded.object_koid = Some(koid);
+    Some(decoded)
+}
+
+/// Returns `None` for unknown scheduling record types, as for records cut short.
+fn decode_scheduling(words: &[u64]) -> Option<Decoded> {
+    let mut reader = Reader::new(words);
+    let header = reader.word()?;
+    reader.skip(1)?; // timestamp
+    match bits(header, 60, 4) {
+        LEGACY_CONTEXT_SWITCH => {
+            reader.thread(bits(header, 28, 8))?;
+            reader.thread(bits(header, 36, 8))?;
+        }
+        CONTEXT_SWITCH => {
+            let outgoing_koid = reader.word()?;
+            let incoming_koid = reader.word()?;
+            reader.refs.koids.extend([outgoing_koid, incoming_koid]);
+            reader.arguments(bits(header, 16, 4))?;
+        }
+        THREAD_WAKEUP => {
+            let waking_koid = reader.word()?;
+            reader.refs.koids.push(waking_koid);
+            reader.arguments(bits(header, 16, 4))?;
+        }
+        _ => return None,
+    }
+    reader.finish(Some(1))
+}
+
+/// The message is always stored inline, so the thread is the only table reference a log
+/// record can make.
+fn decode_log(words: &[u64]) -> Option<Decoded> {
+    let mut reader = Reader::new(words);
+    let header = reader.word()?;
+    reader.skip(1)?; // timestamp
+    reader.thread(bits(header, 32, 8))?;
+    reader.skip(bits(header, 16, 15).div_ceil(8) as usize)?;
+    reader.finish(Some(1))
+}
+
+/// Returns `None` for unknown blob formats, as for records cut short.
+fn decode_large_blob(words: &[u64]) -> Option<Decoded> {
+    let mut reader = Reader::new(words);
+    let header = reader.word()?;
+    let format_header = reader.word()?;
+    reader.string(bits(format_header, 0, 16))?;
+    reader.string(bits(format_header, 16, 16))?;
+
+    let timestamp_word = match bits(header, 40, 4) {
+        BLOB_FORMAT_METADATA => {
+            let timestamp_word = reader.pos;
+            reader.skip(1)?;
+            reader.thread(bits(format_header, 36, 8))?;
+            reader.arguments(bits(format_header, 32, 4))?;
+            Some(timestamp_word)
+        }
+        BLOB_FORMAT_NO_METADATA => None,
+        _ => return None,
+    };
+    reader.finish(timestamp_word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_len_of_large_record() {
+        // Large record spanning 0x1_0000 words, beyond the 12-bit size field
+        let header = LARGE_RECORD_TYPE | (0x1_0000 << 4);
+        assert_eq!(record_len(header), 0x1_0000 * 8);
+
+        // String record of 3 words
+        assert_eq!(record_len(2 | (3 << 4)), 24);
+    }
+
+    #[test]
+    fn test_decode_large_blob_with_metadata() {
+        let words = [
+            LARGE_RECORD_TYPE | (6 << 4),
+            // Category index 1, inline name of 5 bytes, thread index 3
+            1 | ((0x8000 | 5) << 16) | (3 << 36),
+            u64::from_ne_bytes(*b"frame\0\0\0"),
+            1234, // timestamp
+            8,    // blob size
+            0,    // payload
+        ];
+        assert_eq!(
+            decode_large_blob(&words),
+            Some(Decoded {
+                timestamp: Some(1234),
+                timestamp_word: 3,
+                refs: Refs {
+                    strings: vec![1],
+                    threads: vec![3],
+                    koids: vec![],
+                },
+                object_koid: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_context_switch_arguments() {
+        let words = [
+            // Context switch with one argument
+            8 | (6 << 4) | (1 << 16) | (CONTEXT_SWITCH << 60),
+            42, // timestamp
+            10, // outgoing thread koid
+            11, // incoming thread koid
+            // String argument named by index 4 with an inline value of 3 bytes
+            STR_ARGUMENT_TYPE | (2 << 4) | (4 << 16) | ((0x8000 | 3) << 32),
+            u64::from_ne_bytes(*b"abc\0\0\0\0\0"),
+        ];
+        assert_eq!(
+            decode_scheduling(&words),
+            Some(Decoded {
+                timestamp: Some(42),
+                timestamp_word: 1,
+                refs: Refs {
+                    strings: vec![4],
+                    threads: vec![],
+                    koids: vec![10, 11],
+                },
+                object_koid: None,
+            })
+        );
+
+        // An argument running past the end of the record
+        let mut overrun = words;
+        overrun[4] = STR_ARGUMENT_TYPE | (3 << 4) | (4 << 16) | ((0x8000 | 3) << 32);
+        assert_eq!(decode(&overrun), Err(Malformed));
+    }
+
+    #[test]
+    fn test_decode_kernel_object_name_and_arguments() {
+        let words = [
+            // Kernel object named by index 7 with one argument
+            KERNEL_OBJECT_RECORD_TYPE | (3 << 4) | (2 << 16) | (7 << 24) | (1 << 40),
+            1001, // koid
+            // Kernel object id argument named by index 8
+            8 | (2 << 4) | (8 << 16),
+            1000, // process koid
+        ];
+        assert_eq!(
+            decode(&words),
+            Ok(Some(Decoded {
+                timestamp: None,
+                timestamp_word: 0,
+                refs: Refs {
+                    strings: vec![7, 8],
+                    threads: vec![],
+                    koids: vec![1000],
+                },
+                object_koid: Some(1001),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_rebase_duration_complete() {
+        let words = [
+            EVENT_RECORD_TYPE | (4 << 4) | (DURATION_COMPLETE_EVENT << 16),
+            1500, // timestamp
+            0,
+            1600, // end timestamp
+        ];
+        let mut record: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        rebase_event(&mut record, 1000);
+        assert_eq!(to_words(&record), [words[0], 500, 0, 600]);
+
+        // Timestamps before the offset stop at zero
+        rebase_event(&mut record, 550);
+        assert_eq!(to_words(&record), [words[0], 0, 0, 50]);
+    }
+}